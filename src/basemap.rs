@@ -0,0 +1,164 @@
+//! Raster basemap from web mercator XYZ tiles
+use crate::crs::Crs;
+use crate::map::{LoadedArea, Map};
+use crate::tiles::{tile_bbox, TileId, TileRange, TileReader, TileSource, MAX_TILES};
+use crate::transform::{MapCoord, MapTransform};
+use bevy::prelude::*;
+use bevy::render::texture::{Extent3d, TextureDimension, TextureFormat};
+use bevy::sprite::SpriteResizeMode;
+use bevy::utils::{HashMap, HashSet};
+
+/// Basemap tiles are drawn below the map layers
+const BASEMAP_Z: f32 = -1.0;
+
+/// Raster layer with PNG or JPEG tiles
+#[derive(Clone, Debug)]
+pub struct RasterLayer {
+    pub source: TileSource,
+    /// Lowest zoom level of the tiles
+    pub min_zoom: u8,
+    /// Highest zoom level of the tiles. Lower levels are scaled up beyond.
+    pub max_zoom: u8,
+}
+
+/// Background raster layer drawn under the map layers. No basemap if `None`.
+#[derive(Default)]
+pub struct Basemap(pub Option<RasterLayer>);
+
+/// Tags basemap tile sprites
+pub struct BasemapTile;
+
+/// Loaded tiles and the view they were placed for
+#[derive(Default)]
+pub(crate) struct BasemapState {
+    /// Map center, level of detail and loaded area of the placed tiles
+    view: Option<(MapCoord, f32, (f64, f64, f64, f64))>,
+    /// Tile sprites. `None` for missing tiles.
+    tiles: HashMap<TileId, Option<Entity>>,
+}
+
+fn decode_tile(data: &[u8]) -> Option<Texture> {
+    let image = match image::load_from_memory(data) {
+        Ok(image) => image.into_rgba8(),
+        Err(err) => {
+            warn!("Decoding tile failed: {}", err);
+            return None;
+        }
+    };
+    let (width, height) = image.dimensions();
+    Some(Texture::new(
+        Extent3d::new(width, height, 1),
+        TextureDimension::D2,
+        image.into_raw(),
+        TextureFormat::Rgba8UnormSrgb,
+    ))
+}
+
+/// World center and size of a tile. Tiles are approximated by rectangles in map CRS.
+fn tile_rect(id: TileId, crs: Crs, transform: &MapTransform) -> Option<(Vec2, Vec2)> {
+    let mut min = Vec2::splat(f32::INFINITY);
+    let mut max = Vec2::splat(f32::NEG_INFINITY);
+    let (min_x, min_y, max_x, max_y) = tile_bbox(id);
+    let corners = [
+        MapCoord::new(min_x, min_y),
+        MapCoord::new(max_x, min_y),
+        MapCoord::new(min_x, max_y),
+        MapCoord::new(max_x, max_y),
+    ];
+    for corner in &corners {
+        let world = transform.map_to_world(Crs::WEB_MERCATOR.transform_to(*corner, crs)?);
+        min = min.min(world);
+        max = max.max(world);
+    }
+    Some(((min + max) / 2.0, max - min))
+}
+
+/// Load and place the basemap tiles covering the loaded map area
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn update_basemap(
+    commands: &mut Commands,
+    basemap: Res<Basemap>,
+    map: Res<Map>,
+    loaded: Res<LoadedArea>,
+    mut state: Local<BasemapState>,
+    mut textures: ResMut<Assets<Texture>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut sprites: Query<(&mut Transform, &mut Sprite), With<BasemapTile>>,
+) {
+    let (layer, bbox) = match (&basemap.0, loaded.bbox()) {
+        (Some(layer), Some(bbox)) => (layer, bbox),
+        _ => return,
+    };
+    // Place tiles after reloads only
+    let view = (map.center, map.lod(), bbox);
+    if state.view == Some(view) {
+        return;
+    }
+    state.view = Some(view);
+    let z = (map.lod().max(0.0) as u8)
+        .max(layer.min_zoom)
+        .min(layer.max_zoom);
+
+    let range = match TileRange::covering(bbox, map.crs, z) {
+        Some(range) => range,
+        None => {
+            warn!("Basemap not supported for EPSG:{}", map.crs.epsg);
+            return;
+        }
+    };
+    if range.len() > MAX_TILES {
+        warn!("Basemap: too many tiles at zoom level {}", z);
+        return;
+    }
+
+    let transform = MapTransform::from_map(&map);
+    let mut reader = None;
+    let mut visible = HashSet::default();
+    for id in range.iter() {
+        let (center, size) = match tile_rect(id, map.crs, &transform) {
+            Some(rect) => rect,
+            None => continue,
+        };
+        visible.insert(id);
+        // Move placed tiles with the world origin
+        if let Some(placed) = state.tiles.get(&id) {
+            if let Some((mut tile_transform, mut sprite)) =
+                placed.and_then(|entity| sprites.get_mut(entity).ok())
+            {
+                tile_transform.translation = center.extend(BASEMAP_Z);
+                sprite.size = size;
+            }
+            continue;
+        }
+        if reader.is_none() {
+            reader = TileReader::open(&layer.source);
+        }
+        let texture = reader
+            .as_ref()
+            .and_then(|reader| reader.read(id))
+            .and_then(|data| decode_tile(&data));
+        let entity = texture.map(|texture| {
+            commands
+                .spawn(SpriteBundle {
+                    material: materials.add(textures.add(texture).into()),
+                    sprite: Sprite {
+                        size,
+                        resize_mode: SpriteResizeMode::Manual,
+                    },
+                    transform: Transform::from_translation(center.extend(BASEMAP_Z)),
+                    ..Default::default()
+                })
+                .with(BasemapTile);
+            commands.current_entity().unwrap()
+        });
+        state.tiles.insert(id, entity);
+    }
+    // Remove tiles outside of the loaded area
+    state.tiles.retain(|id, entity| {
+        let keep = visible.contains(id);
+        if let (false, Some(entity)) = (keep, entity) {
+            commands.despawn(*entity);
+        }
+        keep
+    });
+}
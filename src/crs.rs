@@ -0,0 +1,201 @@
+//! Coordinate reference systems of map data
+use crate::transform::MapCoord;
+use std::f64::consts::PI;
+
+/// Web Mercator earth radius
+const EARTH_RADIUS: f64 = 6_378_137.0;
+
+/// Meters per degree at the equator
+const METERS_PER_DEGREE: f64 = 2.0 * PI * EARTH_RADIUS / 360.0;
+
+/// Tile size of the web map scale pyramid in pixels
+const TILE_SIZE: f64 = 256.0;
+
+/// Coordinate reference system identified by EPSG code
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Crs {
+    pub epsg: i32,
+}
+
+impl Crs {
+    /// WGS 84 longitude/latitude
+    pub const WGS84: Crs = Crs { epsg: 4326 };
+    /// WGS 84 / Pseudo-Mercator
+    pub const WEB_MERCATOR: Crs = Crs { epsg: 3857 };
+    /// CH1903+ / LV95
+    pub const LV95: Crs = Crs { epsg: 2056 };
+    /// CH1903 / LV03
+    pub const LV03: Crs = Crs { epsg: 21781 };
+
+    /// Coordinates in degrees
+    pub fn is_geographic(&self) -> bool {
+        self.epsg == 4326
+    }
+
+    /// Convert coordinate to WGS 84 longitude/latitude.
+    /// Returns `None` for unsupported CRS.
+    pub fn to_lonlat(&self, coord: MapCoord) -> Option<MapCoord> {
+        match self.epsg {
+            4326 => Some(coord),
+            3857 => Some(MapCoord::new(
+                (coord.x / EARTH_RADIUS).to_degrees(),
+                (2.0 * (coord.y / EARTH_RADIUS).exp().atan() - PI / 2.0).to_degrees(),
+            )),
+            2056 => Some(swiss_to_wgs84(coord.x - 2_000_000.0, coord.y - 1_000_000.0)),
+            21781 => Some(swiss_to_wgs84(coord.x, coord.y)),
+            _ => None,
+        }
+    }
+
+    /// Convert WGS 84 longitude/latitude to a coordinate of this CRS.
+    /// Returns `None` for unsupported CRS.
+    pub fn from_lonlat(&self, lonlat: MapCoord) -> Option<MapCoord> {
+        match self.epsg {
+            4326 => Some(lonlat),
+            3857 => Some(MapCoord::new(
+                EARTH_RADIUS * lonlat.x.to_radians(),
+                EARTH_RADIUS * (PI / 4.0 + lonlat.y.to_radians() / 2.0).tan().ln(),
+            )),
+            2056 => {
+                let lv03 = wgs84_to_swiss(lonlat);
+                Some(MapCoord::new(lv03.x + 2_000_000.0, lv03.y + 1_000_000.0))
+            }
+            21781 => Some(wgs84_to_swiss(lonlat)),
+            _ => None,
+        }
+    }
+
+    /// Convert coordinate to CRS `target`. Returns `None` for unsupported CRS.
+    pub fn transform_to(&self, coord: MapCoord, target: Crs) -> Option<MapCoord> {
+        if *self == target {
+            return Some(coord);
+        }
+        self.to_lonlat(coord)
+            .and_then(|lonlat| target.from_lonlat(lonlat))
+    }
+}
+
+impl Crs {
+    /// Map units per pixel at web map zoom level `zoom`.
+    ///
+    /// Level 0 covers the earth with one 256 pixel tile (156543.03 m/pixel at the equator).
+    /// Projected coordinates are assumed to be in meters.
+    pub fn resolution(&self, zoom: f32) -> f64 {
+        let tiles = 2f64.powf(zoom as f64);
+        if self.is_geographic() {
+            360.0 / (TILE_SIZE * tiles)
+        } else {
+            2.0 * PI * EARTH_RADIUS / (TILE_SIZE * tiles)
+        }
+    }
+
+    /// Ground distance in meters of `size` map units in x direction at `coord`
+    pub fn ground_distance(&self, size: f64, coord: MapCoord) -> f64 {
+        match self.epsg {
+            4326 => size * METERS_PER_DEGREE * coord.y.to_radians().cos(),
+            3857 => {
+                let lat = self.to_lonlat(coord).map(|lonlat| lonlat.y).unwrap_or(0.0);
+                size * lat.to_radians().cos()
+            }
+            _ => size,
+        }
+    }
+}
+
+impl Default for Crs {
+    fn default() -> Self {
+        Crs::WGS84
+    }
+}
+
+/// Approximate conversion from Swiss LV03 coordinates (swisstopo formulas, ~1m accuracy)
+fn swiss_to_wgs84(e: f64, n: f64) -> MapCoord {
+    let y = (e - 600_000.0) / 1_000_000.0;
+    let x = (n - 200_000.0) / 1_000_000.0;
+    let lon = 2.6779094 + 4.728982 * y + 0.791484 * y * x + 0.1306 * y * x * x - 0.0436 * y * y * y;
+    let lat = 16.9023892 + 3.238272 * x
+        - 0.270978 * y * y
+        - 0.002528 * x * x
+        - 0.0447 * y * y * x
+        - 0.0140 * x * x * x;
+    // 10000" units to degrees
+    MapCoord::new(lon * 100.0 / 36.0, lat * 100.0 / 36.0)
+}
+
+/// Approximate conversion to Swiss LV03 coordinates (swisstopo formulas, ~1m accuracy)
+fn wgs84_to_swiss(lonlat: MapCoord) -> MapCoord {
+    // Degrees to 10000" units relative to Bern
+    let lon = (lonlat.x * 3600.0 - 26_782.5) / 10_000.0;
+    let lat = (lonlat.y * 3600.0 - 169_028.66) / 10_000.0;
+    let e = 600_072.37 + 211_455.93 * lon
+        - 10_938.51 * lon * lat
+        - 0.36 * lon * lat * lat
+        - 44.54 * lon * lon * lon;
+    let n = 200_147.07 + 308_807.95 * lat + 3_745.25 * lon * lon + 76.63 * lat * lat
+        - 194.56 * lon * lon * lat
+        + 119.79 * lat * lat * lat;
+    MapCoord::new(e, n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: MapCoord, b: MapCoord, tolerance: f64) {
+        assert!(
+            (a.x - b.x).abs() <= tolerance && (a.y - b.y).abs() <= tolerance,
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn web_mercator() {
+        let crs = Crs::WEB_MERCATOR;
+        assert_near(
+            crs.from_lonlat(MapCoord::new(0.0, 0.0)).unwrap(),
+            MapCoord::new(0.0, 0.0),
+            1e-6,
+        );
+        // Corner of the web mercator square
+        assert_near(
+            crs.from_lonlat(MapCoord::new(180.0, 85.051_128_779_806_6))
+                .unwrap(),
+            MapCoord::new(20_037_508.342_789_244, 20_037_508.342_789_244),
+            1e-3,
+        );
+        let lonlat = MapCoord::new(8.5, 47.3);
+        let coord = crs.from_lonlat(lonlat).unwrap();
+        assert_near(crs.to_lonlat(coord).unwrap(), lonlat, 1e-9);
+    }
+
+    #[test]
+    fn lv95() {
+        let crs = Crs::LV95;
+        // Example of the swisstopo approximate formulas
+        let lonlat = MapCoord::new(
+            8.0 + 43.0 / 60.0 + 49.79 / 3600.0,
+            46.0 + 2.0 / 60.0 + 38.87 / 3600.0,
+        );
+        assert_near(
+            crs.from_lonlat(lonlat).unwrap(),
+            MapCoord::new(2_699_999.76, 1_099_999.97),
+            0.1,
+        );
+        // About one meter
+        assert_near(
+            crs.to_lonlat(MapCoord::new(2_699_999.76, 1_099_999.97))
+                .unwrap(),
+            lonlat,
+            1e-5,
+        );
+    }
+
+    #[test]
+    fn unsupported_crs() {
+        let crs = Crs { epsg: 32632 };
+        assert!(crs.to_lonlat(MapCoord::new(0.0, 0.0)).is_none());
+        assert!(crs.from_lonlat(MapCoord::new(0.0, 0.0)).is_none());
+    }
+}
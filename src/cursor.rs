@@ -0,0 +1,92 @@
+//! Cursor coordinate readout
+use crate::crs::Crs;
+use crate::map::Map;
+use crate::pan_orbit_camera::PanOrbitCamera;
+use crate::transform::{MapCoord, MapTransform};
+use crate::ui::{FONT, FONT_SIZE};
+use bevy::prelude::*;
+
+/// Tags the cursor position text
+pub struct CursorPositionText;
+
+#[derive(Default)]
+pub struct CursorState {
+    reader_cursor: EventReader<CursorMoved>,
+    /// Last cursor position in screen coordinates
+    position: Option<Vec2>,
+}
+
+pub(crate) fn setup_cursor_position(commands: &mut Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(5.0),
+                    top: Val::Px(5.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text {
+                value: String::new(),
+                font: asset_server.load(FONT),
+                style: TextStyle {
+                    font_size: FONT_SIZE,
+                    color: Color::BLACK,
+                    ..Default::default()
+                },
+            },
+            ..Default::default()
+        })
+        .with(CursorPositionText);
+}
+
+/// Show map coordinates under the cursor. Log them with key `C`.
+pub(crate) fn update_cursor_position(
+    mut state: Local<CursorState>,
+    ev_cursor: Res<Events<CursorMoved>>,
+    keys: Res<Input<KeyCode>>,
+    windows: Res<Windows>,
+    map: Res<Map>,
+    cameras: Query<&Transform, With<PanOrbitCamera>>,
+    mut texts: Query<&mut Text, With<CursorPositionText>>,
+) {
+    let latest = state
+        .reader_cursor
+        .iter(&ev_cursor)
+        .last()
+        .map(|ev| ev.position);
+    if latest.is_some() {
+        state.position = latest;
+    }
+    let (position, window, camera) =
+        match (state.position, windows.get_primary(), cameras.iter().next()) {
+            (Some(position), Some(window), Some(camera)) => (position, window, camera),
+            _ => return,
+        };
+    let window_size = Vec2::new(window.width() as f32, window.height() as f32);
+    let transform = MapTransform::from_map(&map)
+        .with_camera(camera.translation.truncate(), camera.scale.x)
+        .with_window_size(window_size);
+    let label = format_position(map.crs, transform.screen_to_map(position));
+    if keys.just_pressed(KeyCode::C) {
+        info!("Cursor position: {}", label);
+    }
+    for mut text in texts.iter_mut() {
+        text.value = label.clone();
+    }
+}
+
+fn format_position(crs: Crs, coord: MapCoord) -> String {
+    if crs.is_geographic() {
+        return format!("{:.6}, {:.6}", coord.x, coord.y);
+    }
+    match crs.to_lonlat(coord) {
+        Some(lonlat) => format!(
+            "{:.1}, {:.1} ({:.6}, {:.6})",
+            coord.x, coord.y, lonlat.x, lonlat.y
+        ),
+        None => format!("{:.1}, {:.1}", coord.x, coord.y),
+    }
+}
@@ -0,0 +1,104 @@
+//! Animated view transitions
+use crate::map::{Map, UpdateMapEvent, ZoomLevels};
+use crate::pan_orbit_camera::PanOrbitCamera;
+use crate::transform::{MapCoord, MapTransform};
+use bevy::prelude::*;
+
+/// Move the view to `center` at zoom level `zoom` within `duration` seconds
+pub struct FlyTo {
+    pub center: MapCoord,
+    pub zoom: f32,
+    pub duration: f32,
+}
+
+/// Running view animation
+struct Animation {
+    start: Vec2,
+    /// Target camera position in world coordinates
+    target: Vec2,
+    start_scale: f32,
+    target_scale: f32,
+    zoom: f32,
+    duration: f32,
+    elapsed: f32,
+}
+
+/// Fly-to animation state. Map reloads are deferred while animating.
+#[derive(Default)]
+pub struct FlyToState {
+    reader: EventReader<FlyTo>,
+    animation: Option<Animation>,
+}
+
+impl FlyToState {
+    pub fn is_animating(&self) -> bool {
+        self.animation.is_some()
+    }
+}
+
+/// Cubic ease-in-out
+fn ease(t: f32) -> f32 {
+    if t < 0.5 {
+        4.0 * t * t * t
+    } else {
+        1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+    }
+}
+
+pub(crate) fn fly_to(
+    time: Res<Time>,
+    mut state: ResMut<FlyToState>,
+    fly_to_events: Res<Events<FlyTo>>,
+    map: Res<Map>,
+    zoom_levels: Res<ZoomLevels>,
+    mut map_events: ResMut<Events<UpdateMapEvent>>,
+    mut query: Query<(&mut PanOrbitCamera, &mut Transform)>,
+) {
+    let state = &mut *state;
+    if let Some(ev) = state.reader.iter(&fly_to_events).last() {
+        if let Some((_, transform)) = query.iter_mut().next() {
+            let zoom = zoom_levels.constrain(ev.zoom);
+            let animation = Animation {
+                start: transform.translation.truncate(),
+                target: MapTransform::from_map(&map).map_to_world(ev.center),
+                start_scale: transform.scale.x,
+                target_scale: map.scale_at(zoom),
+                zoom,
+                duration: ev.duration.max(0.0),
+                elapsed: 0.0,
+            };
+            state.animation = Some(animation);
+        }
+    }
+
+    let animation = match &mut state.animation {
+        Some(animation) => animation,
+        None => return,
+    };
+    animation.elapsed += time.delta_seconds;
+    let t = if animation.duration > 0.0 {
+        (animation.elapsed / animation.duration).min(1.0)
+    } else {
+        1.0
+    };
+    let e = ease(t);
+    let pos = animation.start + (animation.target - animation.start) * e;
+    // Interpolate scale geometrically for a constant zoom speed
+    let scale = animation.start_scale * (animation.target_scale / animation.start_scale).powf(e);
+    for (mut camera, mut transform) in query.iter_mut() {
+        camera.focus = pos.extend(camera.focus.z);
+        transform.translation.x = pos.x;
+        transform.translation.y = pos.y;
+        transform.scale = Vec3::new(scale, scale, 1.0);
+        if t >= 1.0 {
+            camera.zoom = animation.zoom;
+        }
+    }
+    if t >= 1.0 {
+        map_events.send(UpdateMapEvent {
+            offset: Some(animation.target.extend(0.0)),
+            zoom: Some(animation.zoom),
+        });
+        state.animation = None;
+    }
+}
@@ -0,0 +1,532 @@
+//! Feature labels with collision avoidance
+use crate::map::Map;
+use crate::pan_orbit_camera::PanOrbitCamera;
+use crate::transform::{MapCoord, MapTransform};
+use crate::ui::FONT;
+use bevy::prelude::*;
+use bevy::ui::CalculatedSize;
+use bevy::utils::HashMap;
+use geozero::error::Result;
+use geozero::{ColumnValue, GeomProcessor, PropertyProcessor};
+use std::cmp::{Ordering, Reverse};
+
+/// Label placement of a layer
+#[derive(Clone)]
+pub struct LabelStyle {
+    /// Feature attribute with the label text
+    pub attribute: String,
+    pub color: Color,
+    /// Asset path of the label font
+    pub font: String,
+    pub font_size: f32,
+    /// Labels with higher priority are placed first
+    pub priority: i32,
+    /// Smallest zoom level showing labels
+    pub min_zoom: f32,
+    /// Distance between repeated labels along lines in pixels.
+    /// Lines get a single label if not positive.
+    pub spacing: f32,
+}
+
+impl Default for LabelStyle {
+    fn default() -> Self {
+        LabelStyle {
+            attribute: "name".to_string(),
+            color: Color::BLACK,
+            font: FONT.to_string(),
+            font_size: 12.0,
+            priority: 0,
+            min_zoom: 0.0,
+            spacing: 300.0,
+        }
+    }
+}
+
+/// Position of a label
+#[derive(Clone, Debug)]
+pub enum LabelAnchor {
+    /// Label centered at the interior point of polygons or at the point position
+    Point(MapCoord),
+    /// Label following a line, centered `offset` map units from the line start
+    Line { line: Vec<MapCoord>, offset: f64 },
+}
+
+/// Label text at its anchor position
+#[derive(Clone, Debug)]
+pub struct Label {
+    pub text: String,
+    pub anchor: LabelAnchor,
+}
+
+/// Collects labels of features with label attribute.
+///
+/// Process the properties and the geometry of each feature, then call `feature_end`.
+pub struct LabelCollector {
+    attribute: String,
+    /// Label spacing along lines in map units
+    spacing: f64,
+    text: Option<String>,
+    /// Rings of the current polygon or parts of the current line
+    rings: Vec<Vec<MapCoord>>,
+    /// Processing a polygon ring
+    in_polygon: bool,
+    /// Interior point of the widest polygon with the width of its section
+    interior: Option<(MapCoord, f64)>,
+    lines: Vec<Vec<MapCoord>>,
+    /// First point outside of polygons and lines
+    point: Option<MapCoord>,
+    labels: Vec<Label>,
+}
+
+impl LabelCollector {
+    /// Collector for labels of `style` at `resolution` map units per pixel
+    pub fn new(style: &LabelStyle, resolution: f64) -> Self {
+        LabelCollector {
+            attribute: style.attribute.clone(),
+            spacing: style.spacing as f64 * resolution,
+            text: None,
+            rings: Vec::new(),
+            in_polygon: false,
+            interior: None,
+            lines: Vec::new(),
+            point: None,
+            labels: Vec::new(),
+        }
+    }
+
+    /// Add the labels of the processed feature
+    pub fn feature_end(&mut self) {
+        let lines = std::mem::take(&mut self.lines);
+        let position = self.interior.take().map(|(pos, _)| pos).or(self.point);
+        self.rings.clear();
+        self.point = None;
+        let text = match self.text.take() {
+            Some(text) if !text.is_empty() => text,
+            _ => return,
+        };
+        if let Some(position) = position {
+            self.labels.push(Label {
+                text,
+                anchor: LabelAnchor::Point(position),
+            });
+            return;
+        }
+        // Repeat labels along each line part
+        for line in lines {
+            let length = line_length(&line);
+            let count = if self.spacing > 0.0 {
+                (length / self.spacing).floor().max(1.0)
+            } else {
+                1.0
+            };
+            let step = length / count;
+            for i in 0..count as usize {
+                self.labels.push(Label {
+                    text: text.clone(),
+                    anchor: LabelAnchor::Line {
+                        line: line.clone(),
+                        offset: (i as f64 + 0.5) * step,
+                    },
+                });
+            }
+        }
+    }
+
+    pub fn into_labels(self) -> Vec<Label> {
+        self.labels
+    }
+}
+
+impl PropertyProcessor for LabelCollector {
+    fn property(&mut self, _idx: usize, name: &str, value: &ColumnValue) -> Result<bool> {
+        if name != self.attribute {
+            return Ok(false);
+        }
+        self.text = column_text(value);
+        // Stop reading properties
+        Ok(true)
+    }
+}
+
+impl GeomProcessor for LabelCollector {
+    fn xy(&mut self, x: f64, y: f64, _idx: usize) -> Result<()> {
+        let pos = MapCoord::new(x, y);
+        match self.rings.last_mut() {
+            Some(ring) => ring.push(pos),
+            None => {
+                self.point.get_or_insert(pos);
+            }
+        }
+        Ok(())
+    }
+    fn linestring_begin(&mut self, _tagged: bool, size: usize, _idx: usize) -> Result<()> {
+        self.rings.push(Vec::with_capacity(size));
+        Ok(())
+    }
+    fn linestring_end(&mut self, _tagged: bool, _idx: usize) -> Result<()> {
+        if !self.in_polygon {
+            if let Some(line) = self.rings.pop() {
+                if line.len() >= 2 {
+                    self.lines.push(line);
+                }
+            }
+        }
+        Ok(())
+    }
+    fn polygon_begin(&mut self, _tagged: bool, _size: usize, _idx: usize) -> Result<()> {
+        self.in_polygon = true;
+        Ok(())
+    }
+    fn polygon_end(&mut self, _tagged: bool, _idx: usize) -> Result<()> {
+        // Label the widest part of multipolygons
+        if let Some((pos, width)) = interior_point(&self.rings) {
+            if self.interior.map_or(true, |(_, widest)| width > widest) {
+                self.interior = Some((pos, width));
+            }
+        }
+        self.rings.clear();
+        self.in_polygon = false;
+        Ok(())
+    }
+}
+
+/// Label text of an attribute value
+fn column_text(value: &ColumnValue) -> Option<String> {
+    let text = match value {
+        ColumnValue::Byte(v) => v.to_string(),
+        ColumnValue::UByte(v) => v.to_string(),
+        ColumnValue::Bool(v) => v.to_string(),
+        ColumnValue::Short(v) => v.to_string(),
+        ColumnValue::UShort(v) => v.to_string(),
+        ColumnValue::Int(v) => v.to_string(),
+        ColumnValue::UInt(v) => v.to_string(),
+        ColumnValue::Long(v) => v.to_string(),
+        ColumnValue::ULong(v) => v.to_string(),
+        ColumnValue::Float(v) => v.to_string(),
+        ColumnValue::Double(v) => v.to_string(),
+        ColumnValue::String(v) | ColumnValue::DateTime(v) => v.to_string(),
+        _ => return None,
+    };
+    Some(text)
+}
+
+/// Point inside a polygon on the horizontal line through the middle of its extent.
+///
+/// Returns the midpoint of the widest section of the line within the polygon and the
+/// width of this section.
+fn interior_point(rings: &[Vec<MapCoord>]) -> Option<(MapCoord, f64)> {
+    let (min_y, max_y) = rings
+        .first()?
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), pos| {
+            (min.min(pos.y), max.max(pos.y))
+        });
+    if min_y >= max_y {
+        return None;
+    }
+    let y = (min_y + max_y) / 2.0;
+    let mut crossings = Vec::new();
+    for ring in rings {
+        // Segments including the closing segment of unclosed rings
+        for (a, b) in ring.iter().zip(ring.iter().cycle().skip(1)) {
+            // Half-open intervals count vertices on the line once
+            if (a.y > y) != (b.y > y) {
+                crossings.push(a.x + (y - a.y) * (b.x - a.x) / (b.y - a.y));
+            }
+        }
+    }
+    crossings.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    crossings
+        .chunks_exact(2)
+        .map(|section| (section[0], section[1] - section[0]))
+        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))
+        .map(|(x, width)| (MapCoord::new(x + width / 2.0, y), width))
+}
+
+fn line_length(line: &[MapCoord]) -> f64 {
+    line.windows(2)
+        .map(|segment| (segment[1].x - segment[0].x).hypot(segment[1].y - segment[0].y))
+        .sum()
+}
+
+/// Label of a map layer. The text is drawn by the `LabelText` nodes in `glyphs`.
+pub struct MapLabel {
+    /// Layer index
+    pub layer: usize,
+    pub anchor: LabelAnchor,
+    pub priority: i32,
+    pub min_zoom: f32,
+    /// Text node of point labels, one node per character of line labels
+    pub glyphs: Vec<Entity>,
+}
+
+/// Tags label text nodes
+pub struct LabelText;
+
+/// Spawn labels of layer `idx` with their text nodes
+pub(crate) fn spawn_labels(
+    commands: &mut Commands,
+    font: &Handle<Font>,
+    style: &LabelStyle,
+    idx: usize,
+    labels: Vec<Label>,
+) {
+    for label in labels {
+        let texts = match label.anchor {
+            LabelAnchor::Point(_) => vec![label.text],
+            LabelAnchor::Line { .. } => label.text.chars().map(String::from).collect(),
+        };
+        let mut glyphs = Vec::with_capacity(texts.len());
+        for value in texts {
+            commands
+                .spawn(TextBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        ..Default::default()
+                    },
+                    text: Text {
+                        value,
+                        font: font.clone(),
+                        style: TextStyle {
+                            font_size: style.font_size,
+                            color: style.color,
+                            ..Default::default()
+                        },
+                    },
+                    draw: Draw {
+                        // Shown after placement
+                        is_visible: false,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .with(LabelText);
+            glyphs.extend(commands.current_entity());
+        }
+        commands.spawn((MapLabel {
+            layer: idx,
+            anchor: label.anchor,
+            priority: style.priority,
+            min_zoom: style.min_zoom,
+            glyphs,
+        },));
+    }
+}
+
+/// Despawn a label with its text nodes
+pub(crate) fn despawn_label(commands: &mut Commands, entity: Entity, label: &MapLabel) {
+    for glyph in &label.glyphs {
+        commands.despawn(*glyph);
+    }
+    commands.despawn(entity);
+}
+
+/// Minimal distance between labels in pixels
+const LABEL_PADDING: f32 = 2.0;
+
+/// Cell size of the collision index in pixels
+const INDEX_CELL_SIZE: f32 = 64.0;
+
+/// Cosine of the largest direction change between neighbouring glyphs (45°)
+const MIN_GLYPH_ANGLE_COS: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+/// Screen boxes of placed labels in a uniform grid
+#[derive(Default)]
+pub(crate) struct CollisionIndex {
+    boxes: Vec<(Vec2, Vec2)>,
+    cells: HashMap<(i32, i32), Vec<usize>>,
+}
+
+impl CollisionIndex {
+    fn clear(&mut self) {
+        self.boxes.clear();
+        self.cells.clear();
+    }
+
+    fn cells(min: Vec2, max: Vec2) -> impl Iterator<Item = (i32, i32)> {
+        let (x0, y0) = (
+            (min.x / INDEX_CELL_SIZE).floor() as i32,
+            (min.y / INDEX_CELL_SIZE).floor() as i32,
+        );
+        let (x1, y1) = (
+            (max.x / INDEX_CELL_SIZE).floor() as i32,
+            (max.y / INDEX_CELL_SIZE).floor() as i32,
+        );
+        (x0..=x1).flat_map(move |x| (y0..=y1).map(move |y| (x, y)))
+    }
+
+    fn collides(&self, min: Vec2, max: Vec2) -> bool {
+        Self::cells(min, max).any(|cell| {
+            self.cells.get(&cell).map_or(false, |boxes| {
+                boxes.iter().any(|&idx| {
+                    let (other_min, other_max) = self.boxes[idx];
+                    min.x < other_max.x
+                        && other_min.x < max.x
+                        && min.y < other_max.y
+                        && other_min.y < max.y
+                })
+            })
+        })
+    }
+
+    /// Insert the boxes of a label unless one of them collides with a placed box
+    pub(crate) fn try_insert(&mut self, boxes: &[(Vec2, Vec2)]) -> bool {
+        let padding = Vec2::new(LABEL_PADDING, LABEL_PADDING);
+        let padded: Vec<_> = boxes
+            .iter()
+            .map(|(min, max)| (*min - padding, *max + padding))
+            .collect();
+        if padded.iter().any(|(min, max)| self.collides(*min, *max)) {
+            return false;
+        }
+        for (min, max) in padded {
+            let idx = self.boxes.len();
+            self.boxes.push((min, max));
+            for cell in Self::cells(min, max) {
+                self.cells.entry(cell).or_default().push(idx);
+            }
+        }
+        true
+    }
+}
+
+/// Text size in pixels. Estimated until the text layout is computed.
+fn text_size(text: &Text, size: &CalculatedSize) -> Vec2 {
+    if size.size.width > 0.0 {
+        Vec2::new(size.size.width, size.size.height)
+    } else {
+        let font_size = text.style.font_size;
+        Vec2::new(
+            text.value.chars().count() as f32 * font_size * 0.6,
+            font_size,
+        )
+    }
+}
+
+/// Position and unit direction at `distance` pixels along a screen line
+fn along(line: &[Vec2], distance: f32) -> Option<(Vec2, Vec2)> {
+    if distance < 0.0 {
+        return None;
+    }
+    let mut start = 0.0;
+    for segment in line.windows(2) {
+        let delta = segment[1] - segment[0];
+        let length = delta.length();
+        if length > 0.0 && distance <= start + length {
+            let dir = delta / length;
+            return Some((segment[0] + dir * (distance - start), dir));
+        }
+        start += length;
+    }
+    None
+}
+
+/// Glyph centers of a label centered `center` pixels along a screen line.
+///
+/// Glyphs are placed from left to right, so text running against the line direction
+/// is placed in reverse order instead of upside down. The glyphs themselves stay
+/// upright, since bevy UI text can't be rotated.
+/// Returns `None` if the label doesn't fit on the line or the line bends too sharply.
+fn place_on_line(line: &[Vec2], center: f32, sizes: &[Vec2]) -> Option<Vec<Vec2>> {
+    let width: f32 = sizes.iter().map(|size| size.x).sum();
+    let (start, end) = (center - width / 2.0, center + width / 2.0);
+    let flip = along(line, end)?.0.x < along(line, start)?.0.x;
+    let (mut distance, step) = if flip { (end, -1.0) } else { (start, 1.0) };
+    let mut centers = Vec::with_capacity(sizes.len());
+    let mut previous: Option<Vec2> = None;
+    for size in sizes {
+        let (pos, dir) = along(line, distance + step * size.x / 2.0)?;
+        if let Some(previous) = previous {
+            if previous.dot(dir) < MIN_GLYPH_ANGLE_COS {
+                return None;
+            }
+        }
+        previous = Some(dir);
+        centers.push(pos);
+        distance += step * size.x;
+    }
+    Some(centers)
+}
+
+/// Place labels of the current view. Labels are placed greedily in priority order,
+/// labels overlapping a placed label are hidden.
+///
+/// Line labels are placed glyph by glyph along the line. Bevy UI text can't be
+/// rotated, so the glyphs follow the line upright.
+pub(crate) fn place_labels(
+    windows: Res<Windows>,
+    map: Res<Map>,
+    mut index: Local<CollisionIndex>,
+    cameras: Query<&Transform, With<PanOrbitCamera>>,
+    labels: Query<&MapLabel>,
+    mut texts: Query<(&Text, &CalculatedSize, &mut Style, &mut Draw), With<LabelText>>,
+) {
+    let (window, camera) = match (windows.get_primary(), cameras.iter().next()) {
+        (Some(window), Some(camera)) => (window, camera),
+        _ => return,
+    };
+    let window_size = Vec2::new(window.width() as f32, window.height() as f32);
+    let transform = MapTransform::from_map(&map)
+        .with_camera(camera.translation.truncate(), camera.scale.x)
+        .with_window_size(window_size);
+    // Displayed zoom level, including zooming between reloads
+    let zoom = map.lod() - camera.scale.x.log2();
+
+    for (_, _, _, mut draw) in texts.iter_mut() {
+        draw.is_visible = false;
+    }
+    let mut candidates: Vec<&MapLabel> = labels
+        .iter()
+        .filter(|label| zoom >= label.min_zoom)
+        .collect();
+    candidates.sort_by_key(|label| Reverse(label.priority));
+
+    index.clear();
+    for label in candidates {
+        let sizes: Vec<Vec2> = label
+            .glyphs
+            .iter()
+            .filter_map(|glyph| {
+                texts
+                    .get_mut(*glyph)
+                    .ok()
+                    .map(|(text, size, _, _)| text_size(text, size))
+            })
+            .collect();
+        if sizes.len() != label.glyphs.len() {
+            continue;
+        }
+        let centers = match &label.anchor {
+            LabelAnchor::Point(position) => vec![transform.map_to_screen(*position)],
+            LabelAnchor::Line { line, offset } => {
+                let line: Vec<Vec2> = line
+                    .iter()
+                    .map(|pos| transform.map_to_screen(*pos))
+                    .collect();
+                let center = (*offset / transform.pixel_size()) as f32;
+                match place_on_line(&line, center, &sizes) {
+                    Some(centers) => centers,
+                    None => continue,
+                }
+            }
+        };
+        let boxes: Vec<(Vec2, Vec2)> = centers
+            .iter()
+            .zip(&sizes)
+            .map(|(center, size)| (*center - *size / 2.0, *center + *size / 2.0))
+            .collect();
+        let on_screen = boxes.iter().all(|(min, max)| {
+            min.x >= 0.0 && min.y >= 0.0 && max.x <= window_size.x && max.y <= window_size.y
+        });
+        if !on_screen || !index.try_insert(&boxes) {
+            continue;
+        }
+        for (glyph, (min, _)) in label.glyphs.iter().zip(&boxes) {
+            if let Ok((_, _, mut style, mut draw)) = texts.get_mut(*glyph) {
+                style.position.left = Val::Px(min.x);
+                style.position.bottom = Val::Px(min.y);
+                draw.is_visible = true;
+            }
+        }
+    }
+}
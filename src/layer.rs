@@ -0,0 +1,68 @@
+use crate::label::LabelStyle;
+use crate::tiles::TileSource;
+use bevy::prelude::*;
+use lyon::tessellation::FillRule;
+
+/// FlatGeobuf layer configuration
+#[derive(Clone)]
+pub struct FgbLayer {
+    /// Local file path (native)
+    pub path: String,
+    /// HTTP URL (web)
+    pub url: String,
+    pub style: LayerStyle,
+    /// Read features from Mapbox vector tiles instead of the FlatGeobuf file (native)
+    pub tiles: Option<VectorTiles>,
+}
+
+/// Mapbox vector tiles (MVT) of a layer
+#[derive(Clone, Debug)]
+pub struct VectorTiles {
+    pub source: TileSource,
+    /// Name of the layer within the tiles
+    pub layer: String,
+    /// Lowest zoom level of the tiles
+    pub min_zoom: u8,
+    /// Highest zoom level of the tiles. Lower levels are used beyond.
+    pub max_zoom: u8,
+}
+
+/// Map layers in drawing order
+#[derive(Default)]
+pub struct MapLayers(pub Vec<FgbLayer>);
+
+#[derive(Clone)]
+pub struct LayerStyle {
+    pub fill_color: Color,
+    /// Polygon outline. No outlines are drawn if `None`.
+    pub outline: Option<Outline>,
+    /// Fill rule for overlapping rings. Not supported by earcutr.
+    pub fill_rule: FillRule,
+    /// Maximal distance between a curve and its approximation in (physical) pixels.
+    /// Vertices are in pixel units, so the tolerance in map units follows the resolution.
+    pub tolerance: f32,
+    /// Width of line geometries in pixels. Lines are drawn with `fill_color`.
+    pub line_width: f32,
+    /// Feature labels. No labels are placed if `None`.
+    pub label: Option<LabelStyle>,
+}
+
+impl Default for LayerStyle {
+    fn default() -> Self {
+        LayerStyle {
+            fill_color: Color::rgb(0.25, 0.25, 0.25),
+            outline: None,
+            fill_rule: FillRule::EvenOdd,
+            tolerance: 0.1,
+            line_width: 1.0,
+            label: None,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Outline {
+    pub color: Color,
+    /// Line width in pixels
+    pub width: f32,
+}
@@ -0,0 +1,91 @@
+//! FlatGeobuf map rendering with Bevy Engine
+#[cfg(not(target_arch = "wasm32"))]
+mod basemap;
+pub mod crs;
+mod cursor;
+mod fly_to;
+mod instant;
+pub mod label;
+pub mod layer;
+pub mod loader;
+mod map;
+pub mod mesh_builder;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod mvt;
+pub mod pan_orbit_camera;
+pub mod pipeline;
+mod scale_bar;
+pub mod tesselate;
+pub mod tiles;
+pub mod transform;
+pub mod triangulate;
+mod ui;
+mod validate;
+#[cfg(target_arch = "wasm32")]
+mod web_touch;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::basemap::{Basemap, RasterLayer};
+pub use crate::crs::Crs;
+pub use crate::fly_to::{FlyTo, FlyToState};
+pub use crate::label::{LabelAnchor, LabelStyle, LabelText, MapLabel};
+pub use crate::layer::{FgbLayer, LayerStyle, MapLayers, Outline, VectorTiles};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::map::update_map;
+#[cfg(target_arch = "wasm32")]
+use crate::map::update_map_async;
+use crate::map::{
+    pan_or_zoom, resize_map, setup_map, switch_backend, update_labels, zoom_camera, FeatureCaches,
+    LoadedArea,
+};
+pub use crate::map::{Map, MapLayer, UpdateMapEvent, ZoomLevels};
+pub use crate::mesh_builder::{Backend, Cell, FeatureCache, MeshChunk, TessellationStats};
+pub use crate::pan_orbit_camera::InputBindings;
+pub use crate::pipeline::{MapMaterial, MapMeshAttributes};
+pub use crate::tiles::TileSource;
+pub use crate::transform::{MapCoord, MapTransform};
+pub use crate::ui::MapUiPlugin;
+use bevy::prelude::*;
+
+/// Map rendering plugin. Add after bevy's `DefaultPlugins`.
+///
+/// Configure the map with the `Map`, `MapLayers`, `ZoomLevels` and `Backend` resources
+/// and an optional `Basemap` (native only).
+///
+/// Feature labels are drawn as UI text and need a UI camera, e.g. the one spawned by
+/// `MapUiPlugin`.
+pub struct FlatGeobufMapPlugin;
+
+impl Plugin for FlatGeobufMapPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<UpdateMapEvent>()
+            .add_event::<FlyTo>()
+            .init_resource::<Map>()
+            .init_resource::<ZoomLevels>()
+            .init_resource::<MapLayers>()
+            .init_resource::<Backend>()
+            .add_resource(TessellationStats::default())
+            .init_resource::<FlyToState>()
+            .init_resource::<LoadedArea>()
+            .init_resource::<FeatureCaches>()
+            .add_plugin(pan_orbit_camera::PanOrbitCameraPlugin);
+        pipeline::add_map_pipeline(app);
+
+        #[cfg(not(target_arch = "wasm32"))]
+        app.add_system(update_map.system())
+            .init_resource::<basemap::Basemap>()
+            .add_system(basemap::update_basemap.system());
+
+        #[cfg(target_arch = "wasm32")]
+        app.add_system(update_map_async.system());
+
+        app.add_system(fly_to::fly_to.system())
+            .add_system(zoom_camera.system())
+            .add_system(pan_or_zoom.system())
+            .add_system(resize_map.system())
+            .add_system(switch_backend.system())
+            .add_system(update_labels.system())
+            .add_system(label::place_labels.system())
+            .add_startup_system(setup_map.system());
+    }
+}
@@ -0,0 +1,178 @@
+#[cfg(not(target_arch = "wasm32"))]
+use crate::crs::Crs;
+use crate::instant::Instant;
+use crate::label::{Label, LabelCollector};
+use crate::layer::FgbLayer;
+use crate::mesh_builder::{FeatureCache, MapMeshes, MeshBuilder, Triangulator};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::mvt::read_mvt;
+use crate::transform::MapTransform;
+use bevy::prelude::*;
+use bevy::tasks::TaskPool;
+use flatgeobuf::*;
+use geozero::error::Result;
+use geozero::{FeatureProperties, GeomProcessor, PropertyProcessor};
+
+/// Feature read from a layer source
+pub trait LayerFeature {
+    fn process_geometry<P: GeomProcessor>(&self, processor: &mut P) -> Result<()>;
+    fn process_properties<P: PropertyProcessor>(&self, processor: &mut P) -> Result<()>;
+}
+
+/// Tessellates the features of a layer and collects their labels.
+/// Sources only read their features and pass them to `process_feature`.
+pub struct LayerReader<T: Triangulator> {
+    builder: MeshBuilder<T>,
+    labels: Option<LabelCollector>,
+    start: Instant,
+}
+
+impl<T: Triangulator> LayerReader<T> {
+    pub fn new(layer: &FgbLayer, transform: &MapTransform, cache: Option<FeatureCache>) -> Self {
+        let mut builder = MeshBuilder::<T>::new(transform, &layer.style);
+        if let Some(cache) = cache {
+            builder = builder.with_cache(cache);
+        }
+        let labels = layer
+            .style
+            .label
+            .as_ref()
+            .map(|style| LabelCollector::new(style, transform.resolution));
+        LayerReader {
+            builder,
+            labels,
+            start: Instant::now(),
+        }
+    }
+
+    /// Tessellate batches of features in parallel
+    pub fn with_task_pool(mut self, task_pool: TaskPool) -> Self {
+        self.builder = self.builder.with_task_pool(task_pool);
+        self
+    }
+
+    /// Don't stroke outline segments outside of `bbox` for the following features
+    pub fn set_outline_clip(&mut self, bbox: Option<(f64, f64, f64, f64)>) {
+        self.builder.set_outline_clip(bbox);
+    }
+
+    /// Tessellate the geometry of `feature` and collect its label
+    pub fn process_feature<F: LayerFeature>(&mut self, feature: &F) -> Result<()> {
+        feature.process_geometry(&mut self.builder)?;
+        self.builder.feature_end();
+        if let Some(labels) = &mut self.labels {
+            feature.process_properties(labels)?;
+            feature.process_geometry(labels)?;
+            labels.feature_end();
+        }
+        Ok(())
+    }
+
+    /// Meshes of the processed features and their labels
+    pub fn finish(self) -> (MapMeshes, Vec<Label>) {
+        let map_meshes = self.builder.into_meshes();
+        let labels = self
+            .labels
+            .map(LabelCollector::into_labels)
+            .unwrap_or_default();
+        info!(
+            "{}: triangulated in {} ms",
+            std::any::type_name::<T>(),
+            self.start.elapsed().as_millis()
+        );
+        (map_meshes, labels)
+    }
+}
+
+/// FlatGeobuf feature with the geometry type of its file
+struct Feature<'a> {
+    feature: &'a FgbFeature,
+    geometry_type: GeometryType,
+}
+
+impl LayerFeature for Feature<'_> {
+    fn process_geometry<P: GeomProcessor>(&self, processor: &mut P) -> Result<()> {
+        let geometry = self.feature.geometry().unwrap();
+        geometry.process(processor, self.geometry_type)
+    }
+
+    fn process_properties<P: PropertyProcessor>(&self, processor: &mut P) -> Result<()> {
+        self.feature.process_properties(processor).map(|_| ())
+    }
+}
+
+/// Read and tessellate features of a FlatGeobuf or vector tile layer within `bbox`
+/// and collect their labels
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_layer<T: Triangulator>(
+    layer: &FgbLayer,
+    bbox: (f64, f64, f64, f64),
+    crs: Crs,
+    transform: &MapTransform,
+    cache: Option<FeatureCache>,
+    pool: &TaskPool,
+) -> (MapMeshes, Vec<Label>) {
+    match &layer.tiles {
+        Some(tiles) => read_mvt::<T>(layer, tiles, bbox, crs, transform, cache, pool),
+        None => read_fgb::<T>(layer, bbox, transform, cache, pool),
+    }
+}
+
+/// Read and tessellate features within `bbox` and collect their labels.
+/// Features are decoded sequentially and tessellated in parallel on `pool`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_fgb<T: Triangulator>(
+    layer: &FgbLayer,
+    bbox: (f64, f64, f64, f64),
+    transform: &MapTransform,
+    cache: Option<FeatureCache>,
+    pool: &TaskPool,
+) -> (MapMeshes, Vec<Label>) {
+    use std::fs::File;
+    use std::io::BufReader;
+
+    let span = info_span!("read_fgb");
+    let _read_fgb_span = span.enter();
+    let mut reader = LayerReader::<T>::new(layer, transform, cache).with_task_pool(pool.clone());
+    let mut file = BufReader::new(File::open(&layer.path).unwrap());
+    let mut fgb = FgbReader::open(&mut file).unwrap();
+    let geometry_type = fgb.header().geometry_type();
+    fgb.select_bbox(bbox.0, bbox.1, bbox.2, bbox.3).unwrap();
+    while let Some(feature) = fgb.next().unwrap() {
+        reader
+            .process_feature(&Feature {
+                feature,
+                geometry_type,
+            })
+            .unwrap();
+    }
+    reader.finish()
+}
+
+pub async fn read_fgb_http<T: Triangulator>(
+    layer: &FgbLayer,
+    bbox: (f64, f64, f64, f64),
+    transform: &MapTransform,
+    cache: Option<FeatureCache>,
+) -> (MapMeshes, Vec<Label>) {
+    let span = info_span!("read_fgb_http");
+    let _read_fgb_http_span = span.enter();
+    let mut fgb = HttpFgbReader::open(&layer.url).await.unwrap();
+    let geometry_type = fgb.header().geometry_type();
+    let cnt = fgb
+        .select_bbox(bbox.0, bbox.1, bbox.2, bbox.3)
+        .await
+        .unwrap();
+    info!("bbox: {:?}", &bbox);
+    info!(cnt);
+    let mut reader = LayerReader::<T>::new(layer, transform, cache);
+    while let Some(feature) = fgb.next().await.unwrap() {
+        reader
+            .process_feature(&Feature {
+                feature,
+                geometry_type,
+            })
+            .unwrap();
+    }
+    reader.finish()
+}
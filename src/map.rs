@@ -0,0 +1,791 @@
+use crate::crs::Crs;
+use crate::fly_to::FlyToState;
+use crate::instant::Instant;
+use crate::label::{despawn_label, spawn_labels, Label, MapLabel};
+use crate::layer::{FgbLayer, MapLayers};
+use crate::mesh_builder::{Backend, Cell, FeatureCache, MapMeshes, TessellationStats};
+use crate::pan_orbit_camera::{InputState, PanOrbitCamera};
+use crate::pipeline::{map_mesh_bundle, MapMaterial, MapMeshAttributes};
+use crate::tesselate::Lyon;
+use crate::transform::{rotate, MapCoord, MapTransform};
+use crate::triangulate::Earcutr;
+use bevy::prelude::*;
+#[cfg(not(target_arch = "wasm32"))]
+use bevy::tasks::ComputeTaskPool;
+#[cfg(target_arch = "wasm32")]
+use bevy::tasks::IoTaskPool;
+use bevy::utils::HashMap;
+use bevy::window::WindowResized;
+#[cfg(target_arch = "wasm32")]
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// Map view state
+pub struct Map {
+    /// Map coordinates of the world origin
+    pub center: MapCoord,
+    /// panning offset
+    pub offset: Vec3,
+    /// Zoom level of the web map scale pyramid (fractional levels allowed)
+    pub zoom: f32,
+    /// View rotation around the view axis in radians (counter-clockwise)
+    pub rotation: f32,
+    /// CRS of map coordinates
+    pub crs: Crs,
+}
+
+impl Default for Map {
+    fn default() -> Self {
+        Map {
+            center: MapCoord::default(),
+            offset: Vec3::default(),
+            zoom: 0.0,
+            rotation: 0.0,
+            crs: Crs::default(),
+        }
+    }
+}
+
+impl Map {
+    /// Integer zoom level of the loaded geometries (level of detail)
+    pub fn lod(&self) -> f32 {
+        self.zoom.floor()
+    }
+
+    /// Map units per world unit, i.e. per pixel at the level of detail
+    pub fn resolution(&self) -> f64 {
+        self.crs.resolution(self.lod())
+    }
+
+    /// Camera scale (world units per screen pixel) at zoom level `zoom`
+    pub fn scale_at(&self, zoom: f32) -> f32 {
+        2f32.powf(self.lod() - zoom)
+    }
+
+    /// Camera scale of the current zoom level
+    pub fn scale(&self) -> f32 {
+        self.scale_at(self.zoom)
+    }
+}
+
+/// Zoom level limits
+#[derive(Clone, Copy, Debug)]
+pub struct ZoomLevels {
+    pub min: f32,
+    pub max: f32,
+    /// Round to whole zoom levels after zooming
+    pub snap: bool,
+}
+
+impl Default for ZoomLevels {
+    fn default() -> Self {
+        ZoomLevels {
+            min: 0.0,
+            max: 22.0,
+            snap: false,
+        }
+    }
+}
+
+impl ZoomLevels {
+    /// Clamp to limits and snap if enabled
+    pub fn constrain(&self, zoom: f32) -> f32 {
+        let zoom = zoom.max(self.min).min(self.max);
+        if self.snap {
+            zoom.round()
+        } else {
+            zoom
+        }
+    }
+}
+
+/// Tags entities holding map geometries
+pub struct MapLayer;
+
+/// Change map view.
+///
+/// Map geometries are reloaded when the view leaves the loaded area or the level of
+/// detail changes. An event with the current offset only loads a changed view extent,
+/// e.g. after resizing the window. An event without `offset` and `zoom` forces a reload
+/// and re-tessellates all geometries.
+pub struct UpdateMapEvent {
+    pub offset: Option<Vec3>,
+    pub zoom: Option<f32>,
+}
+
+impl UpdateMapEvent {
+    /// Reload and re-tessellate all geometries
+    pub fn forces_reload(&self) -> bool {
+        self.offset.is_none() && self.zoom.is_none()
+    }
+}
+
+/// Map updates received while a load is running (web)
+#[cfg(target_arch = "wasm32")]
+#[derive(Default)]
+pub(crate) struct PendingUpdate {
+    offset: Option<Vec3>,
+    zoom: Option<f32>,
+    /// A received event forces a reload
+    force: bool,
+    received: bool,
+    /// Set while a load task is running
+    loading: Arc<AtomicBool>,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl PendingUpdate {
+    /// Combine with a later event
+    fn add(&mut self, map_event: &UpdateMapEvent) {
+        self.offset = map_event.offset.or(self.offset);
+        self.zoom = map_event.zoom.or(self.zoom);
+        self.force |= map_event.forces_reload();
+        self.received = true;
+    }
+
+    fn is_loading(&self) -> bool {
+        self.loading.load(Ordering::Acquire)
+    }
+
+    /// Combined event and whether it forces a reload
+    fn take(&mut self) -> Option<(UpdateMapEvent, bool)> {
+        if !std::mem::take(&mut self.received) {
+            return None;
+        }
+        let map_event = UpdateMapEvent {
+            offset: self.offset.take(),
+            zoom: self.zoom.take(),
+        };
+        Some((map_event, std::mem::take(&mut self.force)))
+    }
+}
+
+/// Area of the loaded map geometries
+#[derive(Default)]
+pub(crate) struct LoadedArea {
+    bbox: Option<(f64, f64, f64, f64)>,
+}
+
+impl LoadedArea {
+    /// Loaded map extent (minx, miny, maxx, maxy)
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn bbox(&self) -> Option<(f64, f64, f64, f64)> {
+        self.bbox
+    }
+
+    fn contains(&self, bbox: (f64, f64, f64, f64)) -> bool {
+        match self.bbox {
+            Some(loaded) => {
+                loaded.0 <= bbox.0 && loaded.1 <= bbox.1 && loaded.2 >= bbox.2 && loaded.3 >= bbox.3
+            }
+            None => false,
+        }
+    }
+}
+
+/// Tessellated features and chunk entities of a layer
+#[derive(Default)]
+struct LayerCache {
+    features: Option<FeatureCache>,
+    /// Fill and outline entities of each chunk
+    chunks: HashMap<Cell, Vec<Entity>>,
+    /// Loaded labels, spawned by `update_labels`
+    labels: Option<Vec<Label>>,
+}
+
+impl LayerCache {
+    /// Remove all chunk entities from the cache
+    fn drain_entities(&mut self) -> impl Iterator<Item = Entity> + '_ {
+        self.chunks.drain().flat_map(|(_, entities)| entities)
+    }
+}
+
+/// Tessellated features of each layer
+#[derive(Default)]
+pub(crate) struct FeatureCaches(Vec<LayerCache>);
+
+impl FeatureCaches {
+    /// Take the cache of layer `idx` with the transformation for building its meshes.
+    /// Cached features are reused if the resolution is unchanged and the cache origin
+    /// is near the view. Otherwise the features are dropped and all chunks have to be
+    /// replaced.
+    fn take(&mut self, idx: usize, transform: &MapTransform) -> (LayerCache, MapTransform) {
+        if self.0.len() <= idx {
+            self.0.resize_with(idx + 1, LayerCache::default);
+        }
+        let mut cache = std::mem::take(&mut self.0[idx]);
+        match &cache.features {
+            Some(features)
+                if features.resolution == transform.resolution
+                    && transform.map_to_world(features.origin).length() < MAX_CACHE_DISTANCE =>
+            {
+                let layer_transform = MapTransform {
+                    center: features.origin,
+                    ..*transform
+                };
+                (cache, layer_transform)
+            }
+            _ => {
+                cache.features = None;
+                (cache, *transform)
+            }
+        }
+    }
+
+    /// Drop all tessellated features
+    fn invalidate(&mut self) {
+        for cache in &mut self.0 {
+            cache.features = None;
+        }
+    }
+}
+
+/// Maximal distance of the cache origin from the view center in world units
+const MAX_CACHE_DISTANCE: f32 = 100_000.0;
+const PAN_DELAY: u128 = 200;
+const ZOOM_DELAY: u128 = 150;
+const RESIZE_DELAY: u128 = 200;
+/// Additional area loaded around the view (in view sizes)
+const LOAD_MARGIN: f64 = 0.5;
+/// Smallest camera scale within a level of detail
+const MIN_LOD_SCALE: f32 = 0.5;
+
+pub(crate) fn setup_map(map: Res<Map>, mut map_events: ResMut<Events<UpdateMapEvent>>) {
+    map_events.send(UpdateMapEvent {
+        offset: Some(Vec3::default()),
+        zoom: Some(map.zoom),
+    });
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn pan_or_zoom(
+    fly_to: Res<FlyToState>,
+    mut state: ResMut<InputState>,
+    mousebtn: Res<Input<MouseButton>>,
+    mut map: ResMut<Map>,
+    zoom_levels: Res<ZoomLevels>,
+    mut home: Local<Option<(MapCoord, f32)>>,
+    mut map_events: ResMut<Events<UpdateMapEvent>>,
+    mut query: Query<(&mut PanOrbitCamera, &mut Transform)>,
+) {
+    if fly_to.is_animating() {
+        // Reload after the animation has settled
+        return;
+    }
+    // Initial view for resetting
+    let (home_center, home_zoom) = *home.get_or_insert((map.center, map.zoom));
+    let mut offset = None;
+    let mut zoom = None;
+    let motion_paused = state
+        .last_motion
+        .map(|last| last.elapsed().as_millis() > PAN_DELAY)
+        .unwrap_or(false);
+    // set map offset after end of panning
+    if mousebtn.just_released(MouseButton::Left) || motion_paused {
+        for (camera, _) in query.iter_mut().take(1) {
+            offset = Some(camera.focus);
+        }
+        state.last_motion = None;
+    }
+
+    let zoom_paused = state
+        .last_zoom
+        .map(|last| last.elapsed().as_millis() > ZOOM_DELAY)
+        .unwrap_or(false);
+    // set map zoom level after end of zooming
+    if zoom_paused {
+        for (camera, _) in query.iter_mut().take(1) {
+            zoom = Some(zoom_levels.constrain(camera.zoom));
+            // The camera was moved towards the zoom anchor by `zoom_camera`
+            offset = Some(camera.focus);
+        }
+        state.last_zoom = None;
+        state.zoom_anchor = None;
+    }
+    if state.reset_view {
+        let home = MapTransform::from_map(&map)
+            .map_to_world(home_center)
+            .extend(0.0);
+        for (mut camera, mut transform) in query.iter_mut().take(1) {
+            camera.focus = home;
+            camera.zoom = home_zoom;
+            transform.translation = home.truncate().extend(transform.translation.z);
+            transform.rotation = Quat::identity();
+        }
+        map.rotation = 0.0;
+        offset = Some(home);
+        zoom = Some(home_zoom);
+        state.reset_view = false;
+        state.last_motion = None;
+        state.last_zoom = None;
+        state.zoom_anchor = None;
+    }
+    if offset.is_some() || zoom.is_some() {
+        debug!(
+            "map_events.send(UpdateMapEvent offset: {:?} zoom: {:?}",
+            offset, zoom
+        );
+        map_events.send(UpdateMapEvent { offset, zoom });
+    }
+}
+
+/// Zoom with the camera scale between map reloads
+pub(crate) fn zoom_camera(
+    fly_to: Res<FlyToState>,
+    state: Res<InputState>,
+    windows: Res<Windows>,
+    map: Res<Map>,
+    mut query: Query<(&mut PanOrbitCamera, &mut Transform)>,
+) {
+    if fly_to.is_animating() {
+        return;
+    }
+    let window_size = match windows.get_primary() {
+        Some(window) => Vec2::new(window.width() as f32, window.height() as f32),
+        None => return,
+    };
+    for (mut camera, mut transform) in query.iter_mut() {
+        let scale = map.scale_at(camera.zoom);
+        let previous = transform.scale.x;
+        if scale != previous {
+            // Keep the map location under the cursor fixed
+            let anchor = state.zoom_anchor.unwrap_or(window_size / 2.0);
+            let shift =
+                (rotate(anchor - window_size / 2.0, map.rotation) * (previous - scale)).extend(0.0);
+            camera.focus += shift;
+            transform.translation += shift;
+            transform.scale = Vec3::new(scale, scale, 1.0);
+        }
+    }
+}
+
+/// Load the view extent of the new window size after resizing has paused
+pub(crate) fn resize_map(
+    mut resize_reader: Local<EventReader<WindowResized>>,
+    mut last_resize: Local<Option<Instant>>,
+    resize_events: Res<Events<WindowResized>>,
+    map: Res<Map>,
+    mut map_events: ResMut<Events<UpdateMapEvent>>,
+) {
+    if resize_reader.iter(&resize_events).last().is_some() {
+        *last_resize = Some(Instant::now());
+    }
+    let resize_paused = last_resize
+        .map(|last| last.elapsed().as_millis() > RESIZE_DELAY)
+        .unwrap_or(false);
+    if resize_paused {
+        *last_resize = None;
+        // Tessellated features are kept, only the extent changes
+        map_events.send(UpdateMapEvent {
+            offset: Some(map.offset),
+            zoom: None,
+        });
+    }
+}
+
+/// Switch triangulation backend with key `T`
+pub(crate) fn switch_backend(
+    keys: Res<Input<KeyCode>>,
+    mut backend: ResMut<Backend>,
+    mut map_events: ResMut<Events<UpdateMapEvent>>,
+) {
+    if keys.just_pressed(KeyCode::T) {
+        *backend = backend.next();
+        info!("Triangulation backend: {:?}", *backend);
+        // Reload map with unchanged view
+        map_events.send(UpdateMapEvent {
+            offset: None,
+            zoom: None,
+        });
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn update_map(
+    commands: &mut Commands,
+    pool: Res<ComputeTaskPool>,
+    windows: Res<Windows>,
+    map_layers: Res<MapLayers>,
+    backend: Res<Backend>,
+    mut stats: ResMut<TessellationStats>,
+    mut materials: ResMut<Assets<MapMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut map: ResMut<Map>,
+    zoom_levels: Res<ZoomLevels>,
+    mut loaded: ResMut<LoadedArea>,
+    mut caches: ResMut<FeatureCaches>,
+    mut map_event_reader: Local<EventReader<UpdateMapEvent>>,
+    map_events: Res<Events<UpdateMapEvent>>,
+    mut layers: Query<&mut Transform, With<MapLayer>>,
+    mut cameras: Query<(&mut PanOrbitCamera, &mut Transform), Without<MapLayer>>,
+) {
+    use crate::loader::read_layer;
+    if let Some(map_event) = map_event_reader.iter(&map_events).last() {
+        let span = info_span!("update_map");
+        let _update_map_span = span.enter();
+        let window = match windows.get_primary() {
+            Some(window) => window,
+            None => return,
+        };
+        let force = map_event.forces_reload();
+        let update = apply_map_event(
+            window,
+            &mut map,
+            &zoom_levels,
+            &mut loaded,
+            map_event,
+            force,
+        );
+        update_cameras(&map, update.as_ref(), &mut cameras);
+        let update = match update {
+            Some(update) => update,
+            // View within the loaded area
+            None => return,
+        };
+        // Move unchanged chunks with the world origin
+        for mut layer_transform in layers.iter_mut() {
+            layer_transform.translation = update.to_world(layer_transform.translation);
+        }
+        let ViewUpdate {
+            transform, bbox, ..
+        } = update;
+
+        if force {
+            caches.invalidate();
+        }
+        *stats = TessellationStats::default();
+        for (idx, layer) in map_layers.0.iter().enumerate() {
+            let (mut cache, layer_transform) = caches.take(idx, &transform);
+            if cache.features.is_none() {
+                for entity in cache.drain_entities() {
+                    commands.despawn(entity);
+                }
+            }
+            let features = cache.features.take();
+            let crs = map.crs;
+            let (map_meshes, labels) = match *backend {
+                Backend::Lyon => {
+                    read_layer::<Lyon>(layer, bbox, crs, &layer_transform, features, &pool)
+                }
+                Backend::Earcutr => {
+                    read_layer::<Earcutr>(layer, bbox, crs, &layer_transform, features, &pool)
+                }
+            };
+            stats.add(&map_meshes.stats);
+            spawn_layer(
+                commands,
+                &mut materials,
+                &mut meshes,
+                layer,
+                idx,
+                map_meshes,
+                &transform,
+                &mut cache,
+            );
+            cache.labels = Some(labels);
+            caches.0[idx] = cache;
+        }
+        info!("{:?}", *stats);
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn update_map_async(
+    commands: &'static mut Commands,
+    pool: Res<IoTaskPool>,
+    windows: Res<Windows>,
+    map_layers: Res<MapLayers>,
+    backend: Res<Backend>,
+    mut stats: ResMut<'static, TessellationStats>,
+    mut materials: ResMut<'static, Assets<MapMaterial>>,
+    mut meshes: ResMut<'static, Assets<Mesh>>,
+    mut map: ResMut<Map>,
+    zoom_levels: Res<ZoomLevels>,
+    mut loaded: ResMut<LoadedArea>,
+    mut caches: ResMut<'static, FeatureCaches>,
+    mut map_event_reader: Local<EventReader<UpdateMapEvent>>,
+    mut pending: Local<PendingUpdate>,
+    map_events: Res<Events<UpdateMapEvent>>,
+    mut layers: Query<&mut Transform, With<MapLayer>>,
+    mut cameras: Query<(&mut PanOrbitCamera, &mut Transform), Without<MapLayer>>,
+) {
+    use crate::loader::read_fgb_http;
+    for map_event in map_event_reader.iter(&map_events) {
+        pending.add(map_event);
+    }
+    // The running load owns the chunk entities of the caches
+    if pending.is_loading() {
+        return;
+    }
+    if let Some((map_event, force)) = pending.take() {
+        let span = info_span!("update_map");
+        let _update_map_span = span.enter();
+        let window = match windows.get_primary() {
+            Some(window) => window,
+            None => return,
+        };
+        let update = apply_map_event(
+            window,
+            &mut map,
+            &zoom_levels,
+            &mut loaded,
+            &map_event,
+            force,
+        );
+        update_cameras(&map, update.as_ref(), &mut cameras);
+        let update = match update {
+            Some(update) => update,
+            // View within the loaded area
+            None => return,
+        };
+        // Keep previous meshes in place until the new ones are loaded
+        for mut layer_transform in layers.iter_mut() {
+            layer_transform.translation = update.to_world(layer_transform.translation);
+            layer_transform.scale *= update.factor;
+        }
+        let ViewUpdate {
+            transform, bbox, ..
+        } = update;
+        let map_layers = map_layers.0.clone();
+        let backend = *backend;
+        if force {
+            caches.invalidate();
+        }
+        // Chunks of dropped caches are replaced after loading
+        let mut previous = Vec::new();
+        let layer_caches: Vec<_> = (0..map_layers.len())
+            .map(|idx| {
+                let (mut cache, layer_transform) = caches.take(idx, &transform);
+                if cache.features.is_none() {
+                    previous.extend(cache.drain_entities());
+                }
+                (cache, layer_transform)
+            })
+            .collect();
+        let loading = pending.loading.clone();
+        loading.store(true, Ordering::Release);
+        pool.spawn(async move {
+            let mut layer_meshes = Vec::with_capacity(map_layers.len());
+            for (layer, (mut cache, layer_transform)) in map_layers.iter().zip(layer_caches) {
+                let features = cache.features.take();
+                let (map_meshes, labels) = match backend {
+                    Backend::Lyon => {
+                        read_fgb_http::<Lyon>(layer, bbox, &layer_transform, features).await
+                    }
+                    Backend::Earcutr => {
+                        read_fgb_http::<Earcutr>(layer, bbox, &layer_transform, features).await
+                    }
+                };
+                cache.labels = Some(labels);
+                layer_meshes.push((map_meshes, cache));
+            }
+            for entity in previous {
+                commands.despawn(entity);
+            }
+            *stats = TessellationStats::default();
+            for (idx, (layer, (map_meshes, mut cache))) in
+                map_layers.iter().zip(layer_meshes).enumerate()
+            {
+                stats.add(&map_meshes.stats);
+                spawn_layer(
+                    commands,
+                    &mut materials,
+                    &mut meshes,
+                    layer,
+                    idx,
+                    map_meshes,
+                    &transform,
+                    &mut cache,
+                );
+                caches.0[idx] = cache;
+            }
+            info!("{:?}", *stats);
+            loading.store(false, Ordering::Release);
+        });
+    }
+}
+
+/// Replace changed chunk meshes of a layer and store the features in `cache`
+#[allow(clippy::too_many_arguments)]
+fn spawn_layer(
+    commands: &mut Commands,
+    materials: &mut Assets<MapMaterial>,
+    meshes: &mut Assets<Mesh>,
+    layer: &FgbLayer,
+    idx: usize,
+    map_meshes: MapMeshes,
+    transform: &MapTransform,
+    cache: &mut LayerCache,
+) {
+    // Mesh vertices are relative to the cache origin.
+    // Draw layers above each other.
+    let offset = transform
+        .map_to_world(map_meshes.cache.origin)
+        .extend(idx as f32 * 0.2);
+    for cell in &map_meshes.removed {
+        for entity in cache.chunks.remove(cell).unwrap_or_default() {
+            commands.despawn(entity);
+        }
+    }
+    let fill = materials.add(layer.style.fill_color.into());
+    let stroke = layer
+        .style
+        .outline
+        .as_ref()
+        .map(|outline| materials.add(outline.color.into()));
+    for chunk in map_meshes.chunks {
+        for entity in cache.chunks.remove(&chunk.cell).unwrap_or_default() {
+            commands.despawn(entity);
+        }
+        let mut entities = Vec::with_capacity(2);
+        let attributes = MapMeshAttributes::of(&chunk.fill);
+        commands
+            .spawn(map_mesh_bundle(
+                meshes.add(chunk.fill),
+                Transform::from_translation(offset),
+            ))
+            .with(attributes)
+            .with(fill.clone())
+            .with(MapLayer);
+        entities.extend(commands.current_entity());
+        if let (Some(stroke), Some(outline_mesh)) = (&stroke, chunk.outline) {
+            let offset = offset + Vec3::new(0.0, 0.0, 0.1);
+            let attributes = MapMeshAttributes::of(&outline_mesh);
+            commands
+                .spawn(map_mesh_bundle(
+                    meshes.add(outline_mesh),
+                    Transform::from_translation(offset),
+                ))
+                .with(attributes)
+                .with(stroke.clone())
+                .with(MapLayer);
+            entities.extend(commands.current_entity());
+        }
+        cache.chunks.insert(chunk.cell, entities);
+    }
+    cache.features = Some(map_meshes.cache);
+}
+
+/// Replace the labels of reloaded layers
+pub(crate) fn update_labels(
+    commands: &mut Commands,
+    asset_server: Res<AssetServer>,
+    map_layers: Res<MapLayers>,
+    mut caches: ResMut<FeatureCaches>,
+    map_labels: Query<(Entity, &MapLabel)>,
+) {
+    for (idx, cache) in caches.0.iter_mut().enumerate() {
+        let labels = match cache.labels.take() {
+            Some(labels) => labels,
+            None => continue,
+        };
+        for (entity, label) in map_labels.iter() {
+            if label.layer == idx {
+                despawn_label(commands, entity, label);
+            }
+        }
+        if let Some(style) = map_layers
+            .0
+            .get(idx)
+            .and_then(|layer| layer.style.label.as_ref())
+        {
+            spawn_labels(
+                commands,
+                &asset_server.load(style.font.as_str()),
+                style,
+                idx,
+                labels,
+            );
+        }
+    }
+}
+
+/// Change of the world space after a reload
+struct ViewUpdate {
+    /// Transformation for building meshes
+    transform: MapTransform,
+    /// Extent to load
+    bbox: (f64, f64, f64, f64),
+    /// New world origin in previous world coordinates
+    origin: Vec2,
+    /// New world units per previous world unit
+    factor: f32,
+}
+
+impl ViewUpdate {
+    /// Convert from previous to new world coordinates
+    fn to_world(&self, pos: Vec3) -> Vec3 {
+        ((pos.truncate() - self.origin) * self.factor).extend(pos.z)
+    }
+}
+
+/// Apply view changes. Returns `None` if no reload is required.
+/// `force` reloads even if the view is within the loaded area.
+fn apply_map_event(
+    window: &Window,
+    map: &mut Map,
+    zoom_levels: &ZoomLevels,
+    loaded: &mut LoadedArea,
+    map_event: &UpdateMapEvent,
+    force: bool,
+) -> Option<ViewUpdate> {
+    let world = MapTransform::from_map(map);
+    let lod = map.lod();
+    if let Some(offset) = map_event.offset {
+        map.offset = offset;
+    }
+    if let Some(zoom) = map_event.zoom {
+        map.zoom = zoom_levels.constrain(zoom);
+    }
+    let wsize = Vec2::new(window.width() as f32, window.height() as f32);
+    let scale_factor = window.scale_factor() as f32;
+    let center = world.world_to_map(map.offset.truncate());
+    let view = MapTransform::new(center, map.resolution())
+        .with_camera(Vec2::zero(), map.scale())
+        .with_rotation(map.rotation)
+        .with_window_size(wsize);
+    let (minx, miny, maxx, maxy) = view.view_bbox();
+    if !force && map.lod() == lod && loaded.contains((minx, miny, maxx, maxy)) {
+        return None;
+    }
+    // Floating origin: move the world origin to the view center
+    let origin = map.offset.truncate();
+    map.center = center;
+    map.offset = Vec3::zero();
+    let dx = (maxx - minx) * LOAD_MARGIN;
+    let dy = (maxy - miny) * LOAD_MARGIN;
+    let bbox = (minx - dx, miny - dy, maxx + dx, maxy + dy);
+    loaded.bbox = Some(bbox);
+    // Meshes are built in world coordinates
+    let transform = MapTransform::new(center, view.resolution)
+        .with_camera(Vec2::zero(), MIN_LOD_SCALE)
+        .with_window_size(wsize)
+        .with_scale_factor(scale_factor);
+    Some(ViewUpdate {
+        transform,
+        bbox,
+        origin,
+        factor: (world.resolution / view.resolution) as f32,
+    })
+}
+
+/// Move cameras into the world space of the map view
+fn update_cameras(
+    map: &Map,
+    update: Option<&ViewUpdate>,
+    cameras: &mut Query<(&mut PanOrbitCamera, &mut Transform), Without<MapLayer>>,
+) {
+    let scale = map.scale();
+    for (mut camera, mut transform) in cameras.iter_mut() {
+        if let Some(update) = update {
+            camera.focus = update.to_world(camera.focus);
+            transform.translation = update.to_world(transform.translation);
+        }
+        camera.zoom = map.zoom;
+        transform.scale = Vec3::new(scale, scale, 1.0);
+    }
+}
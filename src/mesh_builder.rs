@@ -0,0 +1,977 @@
+use crate::layer::LayerStyle;
+use crate::pipeline::{ATTRIBUTE_COLOR, ATTRIBUTE_EXTRUSION, ATTRIBUTE_FEATURE_ID};
+use crate::tesselate::{stroke_line, stroke_polygon};
+use crate::transform::{MapCoord, MapTransform};
+use crate::validate::{validate_line, validate_polygon};
+use bevy::prelude::*;
+use bevy::render::{mesh, pipeline::PrimitiveTopology};
+use bevy::tasks::TaskPool;
+use bevy::utils::{HashMap, HashSet};
+use geozero::error::Result;
+use geozero::GeomProcessor;
+use lyon::tessellation::{FillOptions, StrokeOptions, TessellationError};
+use std::hash::Hasher;
+use std::marker::PhantomData;
+
+/// Polygon rings in world coordinates of the mesh transform.
+/// The first ring is the exterior ring, followed by the interior rings (holes).
+pub type Polygon = Vec<Vec<[f64; 2]>>;
+
+/// Line vertices in world coordinates of the mesh transform
+pub type Line = Vec<[f64; 2]>;
+
+/// Vertex and index buffers of a triangle mesh
+#[derive(Default)]
+pub struct MeshBuffers {
+    pub vertices: Vec<[f32; 2]>,
+    pub triangles: Vec<u32>, // Max vertices: 4'294'967'295
+    /// Optional vertex colors. Empty or one per vertex.
+    pub colors: Vec<[f32; 4]>,
+    /// Optional vertex feature ids. Empty or one per vertex.
+    /// Chunk meshes use the feature ids of the `FeatureCache`.
+    pub feature_ids: Vec<u32>,
+    /// Optional stroke extrusions in pixels. Empty or one per vertex.
+    /// Stroke vertices are on the stroked path and extruded with the camera scale,
+    /// so strokes keep their width in pixels when zooming.
+    pub extrusions: Vec<[f32; 2]>,
+}
+
+impl MeshBuffers {
+    /// Append vertices with triangle indices relative to the first appended vertex
+    pub fn append(&mut self, vertices: &[[f32; 2]], triangles: &[u32]) {
+        let index_base = self.vertices.len() as u32;
+        self.vertices.extend_from_slice(vertices);
+        self.triangles
+            .extend(triangles.iter().map(|idx| index_base + idx));
+        if !self.extrusions.is_empty() {
+            self.extrusions.resize(self.vertices.len(), [0.0, 0.0]);
+        }
+    }
+
+    /// Append stroke vertices on the stroked path with their extrusions
+    pub fn append_stroke(
+        &mut self,
+        vertices: &[[f32; 2]],
+        extrusions: &[[f32; 2]],
+        triangles: &[u32],
+    ) {
+        // Previous vertices aren't extruded
+        self.extrusions.resize(self.vertices.len(), [0.0, 0.0]);
+        self.extrusions.extend_from_slice(extrusions);
+        let index_base = self.vertices.len() as u32;
+        self.vertices.extend_from_slice(vertices);
+        self.triangles
+            .extend(triangles.iter().map(|idx| index_base + idx));
+    }
+
+    /// Append feature triangles and tag the appended vertices with `feature_id`
+    pub fn append_feature(&mut self, feature: &MeshBuffers, feature_id: u32) {
+        if feature.extrusions.is_empty() {
+            self.append(&feature.vertices, &feature.triangles);
+        } else {
+            self.append_stroke(&feature.vertices, &feature.extrusions, &feature.triangles);
+        }
+        self.feature_ids.resize(self.vertices.len(), feature_id);
+    }
+}
+
+/// Converts mesh buffers into a mesh for the map pipeline.
+impl From<MeshBuffers> for Mesh {
+    fn from(data: MeshBuffers) -> Self {
+        let num_vertices = data.vertices.len();
+        let mut mesh = Self::new(PrimitiveTopology::TriangleList);
+        mesh.set_indices(Some(mesh::Indices::U32(data.triangles)));
+        mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, data.vertices);
+        // Optional attributes select the shader variant (see `MapMeshAttributes`)
+        if !data.colors.is_empty() && data.colors.len() == num_vertices {
+            mesh.set_attribute(ATTRIBUTE_COLOR, data.colors);
+        }
+        if !data.feature_ids.is_empty() && data.feature_ids.len() == num_vertices {
+            mesh.set_attribute(ATTRIBUTE_FEATURE_ID, data.feature_ids);
+        }
+        if !data.extrusions.is_empty() && data.extrusions.len() == num_vertices {
+            mesh.set_attribute(ATTRIBUTE_EXTRUSION, data.extrusions);
+        }
+        mesh
+    }
+}
+
+/// Polygon triangulation backend
+pub trait Triangulator: 'static {
+    /// Backend used when this backend fails to triangulate a polygon
+    type Fallback: Triangulator;
+
+    fn new(options: &FillOptions) -> Self;
+
+    /// Triangulate polygon and append the triangles to `mesh`.
+    /// `mesh` is left unchanged on failure.
+    fn triangulate(
+        &mut self,
+        polygon: &Polygon,
+        mesh: &mut MeshBuffers,
+    ) -> std::result::Result<(), TriangulationError>;
+}
+
+#[derive(Debug)]
+pub enum TriangulationError {
+    /// lyon tessellation failed
+    Tessellation(TessellationError),
+    /// Triangles don't cover the polygon area
+    Coverage,
+}
+
+/// Validation and triangulation failure counters
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TessellationStats {
+    /// Number of processed features
+    pub features: usize,
+    /// Features changed by validation
+    pub invalid_features: usize,
+    /// Number of processed polygons
+    pub polygons: usize,
+    /// Polygons triangulated with the fallback backend
+    pub fallbacks: usize,
+    /// Polygons triangulated without holes
+    pub repaired: usize,
+    /// Polygons skipped, because all attempts failed
+    pub failed: usize,
+    /// Polygons without outline, because stroking failed
+    pub stroke_failed: usize,
+    /// Number of processed lines
+    pub lines: usize,
+    /// Lines skipped, because stroking failed
+    pub line_failed: usize,
+    /// Features reused from the previous update
+    pub cached: usize,
+}
+
+impl TessellationStats {
+    pub fn add(&mut self, other: &TessellationStats) {
+        self.features += other.features;
+        self.invalid_features += other.invalid_features;
+        self.polygons += other.polygons;
+        self.fallbacks += other.fallbacks;
+        self.repaired += other.repaired;
+        self.failed += other.failed;
+        self.stroke_failed += other.stroke_failed;
+        self.lines += other.lines;
+        self.line_failed += other.line_failed;
+        self.cached += other.cached;
+    }
+}
+
+/// Grid cell of a mesh chunk
+pub type Cell = (i32, i32);
+
+/// Size of mesh chunks in world units
+const CHUNK_SIZE: f32 = 1024.0;
+
+/// Fill and outline meshes of the features in a grid cell
+pub struct MeshChunk {
+    pub cell: Cell,
+    pub fill: Mesh,
+    pub outline: Option<Mesh>,
+}
+
+/// Changed mesh chunks with tessellation statistics
+pub struct MapMeshes {
+    /// Chunks with added or removed features
+    pub chunks: Vec<MeshChunk>,
+    /// Chunks without remaining features
+    pub removed: Vec<Cell>,
+    pub stats: TessellationStats,
+    /// Tessellated features for the next update
+    pub cache: FeatureCache,
+}
+
+/// Fill and outline triangles of a feature
+#[derive(Default)]
+struct FeatureMeshes {
+    fill: MeshBuffers,
+    outline: MeshBuffers,
+    /// Chunk containing the first vertex
+    cell: Cell,
+    /// Vertex feature id, assigned by the cache
+    id: u32,
+}
+
+/// Tessellated features of a layer keyed by a hash of their geometry.
+///
+/// The FlatGeobuf feature index would be the natural key, but the `FgbReader` of
+/// flatgeobuf 0.4 doesn't expose the index of features selected by a bbox query.
+/// Features with identical geometries get distinct keys by their order in the dataset,
+/// so none of them is dropped.
+/// TODO: Key features by their index, once the reader exposes it.
+///
+/// Cached features get a feature id for the `Vertex_FeatureId` attribute of the chunk
+/// meshes. Ids are unique among the cached features and kept while a feature stays
+/// cached. `key` maps them back to the feature key.
+/// Vertices are in world coordinates with origin at `origin`.
+pub struct FeatureCache {
+    pub origin: MapCoord,
+    /// Map units per world unit
+    pub resolution: f64,
+    features: HashMap<u64, FeatureMeshes>,
+    /// Chunks with added or removed features since the last merge
+    dirty: HashSet<Cell>,
+    /// Feature id of the next inserted feature
+    next_id: u32,
+}
+
+impl FeatureCache {
+    pub fn new(origin: MapCoord, resolution: f64) -> Self {
+        FeatureCache {
+            origin,
+            resolution,
+            features: HashMap::default(),
+            dirty: HashSet::default(),
+            next_id: 0,
+        }
+    }
+
+    /// Number of cached features
+    pub fn len(&self) -> usize {
+        self.features.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.features.is_empty()
+    }
+
+    /// Key of the cached feature with vertex feature id `feature_id`
+    pub fn key(&self, feature_id: u32) -> Option<u64> {
+        self.features
+            .iter()
+            .find(|(_, feature)| feature.id == feature_id)
+            .map(|(key, _)| *key)
+    }
+
+    fn insert(&mut self, key: u64, mut feature: FeatureMeshes) {
+        feature.id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.dirty.insert(feature.cell);
+        self.features.insert(key, feature);
+    }
+
+    /// Keep features with keys in `keys`
+    fn retain(&mut self, keys: &HashSet<u64>) {
+        let dirty = &mut self.dirty;
+        self.features.retain(|key, feature| {
+            let keep = keys.contains(key);
+            if !keep {
+                dirty.insert(feature.cell);
+            }
+            keep
+        });
+    }
+
+    /// Merge feature triangles of changed chunks into chunk meshes.
+    /// Returns the changed chunks and the removed chunks.
+    fn merge(&mut self, outline: bool) -> (Vec<MeshChunk>, Vec<Cell>) {
+        let (buffers, removed) = self.merge_buffers();
+        let chunks = buffers
+            .into_iter()
+            .map(|(cell, (fill_buffers, outline_buffers))| MeshChunk {
+                cell,
+                fill: fill_buffers.into(),
+                outline: if outline {
+                    Some(outline_buffers.into())
+                } else {
+                    None
+                },
+            })
+            .collect();
+        (chunks, removed)
+    }
+
+    /// Fill and outline buffers of the changed chunks and the removed chunks
+    fn merge_buffers(&mut self) -> (HashMap<Cell, (MeshBuffers, MeshBuffers)>, Vec<Cell>) {
+        let mut buffers: HashMap<Cell, (MeshBuffers, MeshBuffers)> = HashMap::default();
+        for feature in self.features.values() {
+            if !self.dirty.contains(&feature.cell) {
+                continue;
+            }
+            let (fill_buffers, outline_buffers) = buffers.entry(feature.cell).or_default();
+            fill_buffers.append_feature(&feature.fill, feature.id);
+            outline_buffers.append_feature(&feature.outline, feature.id);
+        }
+        let removed = self
+            .dirty
+            .drain()
+            .filter(|cell| !buffers.contains_key(cell))
+            .collect();
+        (buffers, removed)
+    }
+}
+
+/// Available triangulation backends
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Backend {
+    /// lyon fill tessellator
+    Lyon,
+    /// earcutr polygon triangulation
+    Earcutr,
+}
+
+impl Default for Backend {
+    fn default() -> Self {
+        Backend::Lyon
+    }
+}
+
+impl Backend {
+    pub fn next(self) -> Self {
+        match self {
+            Backend::Lyon => Backend::Earcutr,
+            Backend::Earcutr => Backend::Lyon,
+        }
+    }
+}
+
+/// Number of features triangulated in one task
+const BATCH_SIZE: usize = 256;
+
+/// Stroke options of polygon outlines and lines
+#[derive(Clone, Copy)]
+struct StrokeStyle {
+    /// Outline stroke options. No outline is tessellated if `None`.
+    outline: Option<StrokeOptions>,
+    line: StrokeOptions,
+}
+
+/// Triangulates polygons with fallbacks and strokes outlines and lines
+struct Tessellator<T: Triangulator> {
+    triangulator: T,
+    fallback: T::Fallback,
+    stroke: StrokeStyle,
+    stats: TessellationStats,
+}
+
+impl<T: Triangulator> Tessellator<T> {
+    fn new(fill_options: &FillOptions, stroke: StrokeStyle) -> Self {
+        Tessellator {
+            triangulator: T::new(fill_options),
+            fallback: T::Fallback::new(fill_options),
+            stroke,
+            stats: TessellationStats::default(),
+        }
+    }
+
+    /// Tessellate fill and outline of feature polygons and feature lines.
+    /// Outline segments outside of `clip` aren't stroked.
+    fn tessellate(
+        &mut self,
+        polygons: &[Polygon],
+        lines: &[Line],
+        clip: Option<&Bbox>,
+    ) -> FeatureMeshes {
+        let mut feature = FeatureMeshes::default();
+        for polygon in polygons {
+            self.fill_polygon(polygon, &mut feature.fill);
+            if let Some(stroke_options) = &self.stroke.outline {
+                if stroke_outline(polygon, clip, stroke_options, &mut feature.outline).is_err() {
+                    self.stats.stroke_failed += 1;
+                }
+            }
+        }
+        // Lines are part of the fill mesh
+        for line in lines {
+            self.stats.lines += 1;
+            if stroke_line(line, &self.stroke.line, &mut feature.fill).is_err() {
+                self.stats.line_failed += 1;
+            }
+        }
+        let first = polygons
+            .first()
+            .map(|polygon| &polygon[0])
+            .or_else(|| lines.first())
+            .and_then(|vertices| vertices.first());
+        if let Some([x, y]) = first {
+            feature.cell = (
+                (*x as f32 / CHUNK_SIZE).floor() as i32,
+                (*y as f32 / CHUNK_SIZE).floor() as i32,
+            );
+        }
+        feature
+    }
+
+    /// Triangulate polygon. Falls back to the other backend and
+    /// to the polygon without holes, if triangulation fails.
+    fn fill_polygon(&mut self, polygon: &Polygon, fill: &mut MeshBuffers) {
+        self.stats.polygons += 1;
+        let err = match self.triangulator.triangulate(polygon, fill) {
+            Ok(()) => return,
+            Err(err) => err,
+        };
+        if self.fallback.triangulate(polygon, fill).is_ok() {
+            debug!("Triangulation failed ({:?}), used fallback", err);
+            self.stats.fallbacks += 1;
+            return;
+        }
+        // Last resort: holes touching or crossing the exterior ring
+        let exterior = polygon[..1].to_vec();
+        if self.triangulator.triangulate(&exterior, fill).is_ok()
+            || self.fallback.triangulate(&exterior, fill).is_ok()
+        {
+            self.stats.repaired += 1;
+        } else {
+            warn!("Triangulation of polygon failed: {:?}", err);
+            self.stats.failed += 1;
+        }
+    }
+}
+
+/// Extent (minx, miny, maxx, maxy) in world coordinates
+type Bbox = (f64, f64, f64, f64);
+
+/// Parts of a closed ring without the segments outside of `clip`.
+/// Segments on the boundary are outside. Returns `None` if no segment is outside.
+fn clip_ring(ring: &[[f64; 2]], clip: &Bbox) -> Option<Vec<Line>> {
+    let outside = |[x0, y0]: [f64; 2], [x1, y1]: [f64; 2]| {
+        (x0 <= clip.0 && x1 <= clip.0)
+            || (y0 <= clip.1 && y1 <= clip.1)
+            || (x0 >= clip.2 && x1 >= clip.2)
+            || (y0 >= clip.3 && y1 >= clip.3)
+    };
+    let first = ring
+        .windows(2)
+        .position(|segment| outside(segment[0], segment[1]))?;
+    // Start after an outside segment, so parts don't wrap around the ring start
+    let num_segments = ring.len() - 1;
+    let mut parts = Vec::new();
+    let mut part: Line = Vec::new();
+    for k in 1..=num_segments {
+        let i = (first + k) % num_segments;
+        let (start, end) = (ring[i], ring[i + 1]);
+        if outside(start, end) {
+            if part.len() > 1 {
+                parts.push(std::mem::take(&mut part));
+            }
+            part.clear();
+        } else {
+            if part.is_empty() {
+                part.push(start);
+            }
+            part.push(end);
+        }
+    }
+    Some(parts)
+}
+
+/// Stroke the polygon rings without their segments outside of `clip`
+fn stroke_outline(
+    polygon: &Polygon,
+    clip: Option<&Bbox>,
+    options: &StrokeOptions,
+    outline: &mut MeshBuffers,
+) -> std::result::Result<(), TriangulationError> {
+    let clip = match clip {
+        Some(clip) => clip,
+        None => return stroke_polygon(polygon, options, outline),
+    };
+    for ring in polygon {
+        match clip_ring(ring, clip) {
+            Some(parts) => {
+                for part in &parts {
+                    stroke_line(part, options, outline)?;
+                }
+            }
+            None => stroke_polygon(&vec![ring.clone()], options, outline)?,
+        }
+    }
+    Ok(())
+}
+
+/// 64 bit FNV-1a hash of feature geometries.
+/// Unlike `DefaultHasher`, the hash doesn't change between Rust versions and platforms.
+struct FeatureHasher(u64);
+
+impl Default for FeatureHasher {
+    fn default() -> Self {
+        FeatureHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for FeatureHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+}
+
+/// Feature key with validated polygons and lines and the outline clip extent
+type PendingFeature = (u64, Vec<Polygon>, Vec<Line>, Option<Bbox>);
+
+/// Tessellate a batch of features
+fn tessellate_batch<T: Triangulator>(
+    batch: &[PendingFeature],
+    fill_options: &FillOptions,
+    stroke: StrokeStyle,
+) -> (Vec<(u64, FeatureMeshes)>, TessellationStats) {
+    let mut tessellator = Tessellator::<T>::new(fill_options, stroke);
+    let features = batch
+        .iter()
+        .map(|(key, polygons, lines, clip)| {
+            (*key, tessellator.tessellate(polygons, lines, clip.as_ref()))
+        })
+        .collect();
+    (features, tessellator.stats)
+}
+
+/// Collects geometries and builds fill and outline meshes with triangulator `T`
+pub struct MeshBuilder<T: Triangulator> {
+    /// Transformation from map to mesh coordinates
+    transform: MapTransform,
+    fill_options: FillOptions,
+    stroke: StrokeStyle,
+    /// Rings of the current polygon or parts of the current line
+    polygon: Polygon,
+    /// Processing a polygon ring
+    in_polygon: bool,
+    /// Validated polygons
+    valid: Vec<Polygon>,
+    /// Validated lines
+    lines: Vec<Line>,
+    /// Current feature was changed by validation
+    feature_changed: bool,
+    /// Geometry hash of the current feature
+    hasher: FeatureHasher,
+    /// Keys of the processed features
+    processed: HashSet<u64>,
+    /// Features to tessellate
+    pending: Vec<PendingFeature>,
+    /// Outline segments outside of this extent aren't stroked
+    outline_clip: Option<Bbox>,
+    /// Pool for parallel tessellation
+    task_pool: Option<TaskPool>,
+    cache: FeatureCache,
+    stats: TessellationStats,
+    triangulator: PhantomData<T>,
+}
+
+impl<T: Triangulator> MeshBuilder<T> {
+    pub fn new(transform: &MapTransform, style: &LayerStyle) -> Self {
+        // Tolerance in world units for physical pixels at the smallest camera scale
+        let tolerance = style.tolerance * transform.scale / transform.scale_factor;
+        MeshBuilder {
+            transform: *transform,
+            fill_options: FillOptions::tolerance(tolerance).with_fill_rule(style.fill_rule),
+            stroke: StrokeStyle {
+                outline: style.outline.as_ref().map(|outline| {
+                    StrokeOptions::tolerance(tolerance).with_line_width(outline.width)
+                }),
+                line: StrokeOptions::tolerance(tolerance).with_line_width(style.line_width),
+            },
+            polygon: Vec::new(),
+            in_polygon: false,
+            valid: Vec::new(),
+            lines: Vec::new(),
+            feature_changed: false,
+            hasher: FeatureHasher::default(),
+            processed: HashSet::default(),
+            pending: Vec::new(),
+            outline_clip: None,
+            task_pool: None,
+            cache: FeatureCache::new(transform.center, transform.resolution),
+            stats: TessellationStats::default(),
+            triangulator: PhantomData,
+        }
+    }
+
+    /// Reuse features tessellated with the same transformation.
+    /// Features not processed by this builder are dropped from the cache.
+    pub fn with_cache(mut self, cache: FeatureCache) -> Self {
+        if cache.origin == self.transform.center && cache.resolution == self.transform.resolution {
+            self.cache = cache;
+        }
+        self
+    }
+
+    /// Tessellate batches of features in parallel
+    pub fn with_task_pool(mut self, task_pool: TaskPool) -> Self {
+        self.task_pool = Some(task_pool);
+        self
+    }
+
+    /// Don't stroke outline segments outside of `bbox` (in map coordinates) for the
+    /// following features, e.g. edges of polygons clipped to a vector tile.
+    pub fn set_outline_clip(&mut self, bbox: Option<(f64, f64, f64, f64)>) {
+        self.outline_clip = bbox.map(|(minx, miny, maxx, maxy)| {
+            let [x0, y0] = self.transform.map_to_world_f64(MapCoord::new(minx, miny));
+            let [x1, y1] = self.transform.map_to_world_f64(MapCoord::new(maxx, maxy));
+            (x0.min(x1), y0.min(y1), x0.max(x1), y0.max(y1))
+        });
+    }
+
+    /// Chunk meshes changed by the processed features
+    pub fn into_meshes(mut self) -> MapMeshes {
+        self.tessellate_pending();
+        self.cache.retain(&self.processed);
+        let (chunks, removed) = self.cache.merge(self.stroke.outline.is_some());
+        MapMeshes {
+            chunks,
+            removed,
+            stats: self.stats,
+            cache: self.cache,
+        }
+    }
+
+    /// Queue the processed feature for tessellation, unless it is cached.
+    /// Call after processing the geometry of each feature.
+    pub fn feature_end(&mut self) {
+        let mut key = std::mem::take(&mut self.hasher).finish();
+        // Identical geometries are read in the same order by every query
+        while self.processed.contains(&key) {
+            let mut hasher = FeatureHasher::default();
+            hasher.write_u64(key);
+            key = hasher.finish();
+        }
+        self.processed.insert(key);
+        self.stats.features += 1;
+        if self.feature_changed {
+            self.stats.invalid_features += 1;
+            self.feature_changed = false;
+        }
+        let valid = std::mem::take(&mut self.valid);
+        let lines = std::mem::take(&mut self.lines);
+        let cached = self.cache.features.contains_key(&key);
+        if cached {
+            self.stats.cached += 1;
+        }
+        if !cached {
+            self.pending.push((key, valid, lines, self.outline_clip));
+        } else {
+            // Reuse allocations
+            self.valid = valid;
+            self.valid.clear();
+            self.lines = lines;
+            self.lines.clear();
+        }
+    }
+
+    /// Tessellate queued features, in parallel if a task pool is set
+    fn tessellate_pending(&mut self) {
+        let pending = std::mem::take(&mut self.pending);
+        let fill_options = self.fill_options;
+        let stroke = self.stroke;
+        let results = match &self.task_pool {
+            Some(pool) if pending.len() > BATCH_SIZE => pool.scope(|scope| {
+                for batch in pending.chunks(BATCH_SIZE) {
+                    scope.spawn(async move { tessellate_batch::<T>(batch, &fill_options, stroke) });
+                }
+            }),
+            _ => vec![tessellate_batch::<T>(&pending, &fill_options, stroke)],
+        };
+        for (features, stats) in results {
+            self.stats.add(&stats);
+            for (key, feature) in features {
+                self.cache.insert(key, feature);
+            }
+        }
+    }
+}
+
+impl<T: Triangulator> GeomProcessor for MeshBuilder<T> {
+    fn xy(&mut self, x: f64, y: f64, _idx: usize) -> Result<()> {
+        self.hasher.write_u64(x.to_bits());
+        self.hasher.write_u64(y.to_bits());
+        let pos = self.transform.map_to_world_f64(MapCoord::new(x, y));
+        if let Some(ring) = self.polygon.last_mut() {
+            ring.push(pos);
+        }
+        Ok(())
+    }
+    fn linestring_begin(&mut self, _tagged: bool, size: usize, _idx: usize) -> Result<()> {
+        // Ring boundaries are part of the feature key
+        self.hasher.write_usize(size);
+        self.polygon.push(Vec::with_capacity(size));
+        Ok(())
+    }
+    fn linestring_end(&mut self, _tagged: bool, _idx: usize) -> Result<()> {
+        if !self.in_polygon {
+            if let Some(line) = self.polygon.pop() {
+                self.feature_changed |= validate_line(line, &mut self.lines);
+            }
+        }
+        Ok(())
+    }
+    fn polygon_begin(&mut self, _tagged: bool, _size: usize, _idx: usize) -> Result<()> {
+        self.in_polygon = true;
+        Ok(())
+    }
+    fn polygon_end(&mut self, _tagged: bool, _idx: usize) -> Result<()> {
+        // Polygons are tessellated in `feature_end`
+        self.feature_changed |= validate_polygon(&self.polygon, &mut self.valid);
+        self.polygon.clear();
+        self.in_polygon = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer::Outline;
+    use crate::tesselate::Lyon;
+
+    fn square() -> Vec<[f64; 2]> {
+        vec![
+            [0.0, 0.0],
+            [10.0, 0.0],
+            [10.0, 10.0],
+            [0.0, 10.0],
+            [0.0, 0.0],
+        ]
+    }
+
+    #[test]
+    fn ring_inside_clip() {
+        assert!(clip_ring(&square(), &(-1.0, -1.0, 11.0, 11.0)).is_none());
+    }
+
+    #[test]
+    fn clip_edge_on_boundary() {
+        // The right edge is on the clip boundary
+        let parts = clip_ring(&square(), &(-1.0, -1.0, 10.0, 11.0)).unwrap();
+        assert_eq!(
+            parts,
+            vec![vec![[10.0, 10.0], [0.0, 10.0], [0.0, 0.0], [10.0, 0.0]]]
+        );
+    }
+
+    #[test]
+    fn clip_edges_outside() {
+        // Left and right edges are outside
+        let parts = clip_ring(&square(), &(1.0, -1.0, 9.0, 11.0)).unwrap();
+        assert_eq!(
+            parts,
+            vec![
+                vec![[10.0, 10.0], [0.0, 10.0]],
+                vec![[0.0, 0.0], [10.0, 0.0]]
+            ]
+        );
+    }
+
+    /// Process a square feature with its lower left corner at (x, y)
+    fn process_square<T: Triangulator>(builder: &mut MeshBuilder<T>, x: f64, y: f64) {
+        builder.polygon_begin(false, 1, 0).unwrap();
+        builder.linestring_begin(false, 5, 0).unwrap();
+        for (idx, [dx, dy]) in square().iter().enumerate() {
+            builder.xy(x + dx, y + dy, idx).unwrap();
+        }
+        builder.linestring_end(false, 0).unwrap();
+        builder.polygon_end(false, 0).unwrap();
+        builder.feature_end();
+    }
+
+    fn builder(cache: Option<FeatureCache>) -> MeshBuilder<Lyon> {
+        let transform = MapTransform::new(MapCoord::new(0.0, 0.0), 1.0);
+        let builder = MeshBuilder::<Lyon>::new(&transform, &LayerStyle::default());
+        match cache {
+            Some(cache) => builder.with_cache(cache),
+            None => builder,
+        }
+    }
+
+    /// Sorted cells of `chunks`
+    fn cells(chunks: &[MeshChunk]) -> Vec<Cell> {
+        let mut cells: Vec<Cell> = chunks.iter().map(|chunk| chunk.cell).collect();
+        cells.sort();
+        cells
+    }
+
+    fn feature(cell: Cell) -> FeatureMeshes {
+        FeatureMeshes {
+            cell,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn feature_hash_is_stable() {
+        let mut hasher = FeatureHasher::default();
+        hasher.write(b"a");
+        assert_eq!(hasher.finish(), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn cache_retain() {
+        let mut cache = FeatureCache::new(MapCoord::new(0.0, 0.0), 1.0);
+        cache.insert(1, feature((0, 0)));
+        cache.insert(2, feature((1, 0)));
+        cache.merge(false);
+        let keys = [1].iter().copied().collect();
+        cache.retain(&keys);
+        assert_eq!(cache.len(), 1);
+        let (chunks, removed) = cache.merge(false);
+        assert!(chunks.is_empty());
+        assert_eq!(removed, vec![(1, 0)]);
+    }
+
+    #[test]
+    fn cache_merges_dirty_cells() {
+        let mut cache = FeatureCache::new(MapCoord::new(0.0, 0.0), 1.0);
+        cache.insert(1, feature((0, 0)));
+        cache.insert(2, feature((1, 0)));
+        let (chunks, removed) = cache.merge(true);
+        assert_eq!(cells(&chunks), vec![(0, 0), (1, 0)]);
+        assert!(chunks.iter().all(|chunk| chunk.outline.is_some()));
+        assert!(removed.is_empty());
+        // Merged cells are clean
+        cache.insert(3, feature((0, 0)));
+        let (chunks, removed) = cache.merge(false);
+        assert_eq!(cells(&chunks), vec![(0, 0)]);
+        assert!(removed.is_empty());
+    }
+
+    #[test]
+    fn cache_feature_ids() {
+        let mut cache = FeatureCache::new(MapCoord::new(0.0, 0.0), 1.0);
+        cache.insert(10, feature((0, 0)));
+        cache.insert(20, feature((0, 0)));
+        let id = cache.features[&20].id;
+        assert_ne!(cache.features[&10].id, id);
+        assert_eq!(cache.key(id), Some(20));
+        cache.retain(&[20].iter().copied().collect());
+        cache.insert(30, feature((0, 0)));
+        // Ids of cached features are kept and not reused
+        assert_eq!(cache.features[&20].id, id);
+        assert_ne!(cache.features[&30].id, id);
+    }
+
+    #[test]
+    fn builder_updates_changed_cells() {
+        let mut first = builder(None);
+        process_square(&mut first, 0.0, 0.0);
+        process_square(&mut first, 2048.0, 0.0);
+        let meshes = first.into_meshes();
+        assert_eq!(cells(&meshes.chunks), vec![(0, 0), (2, 0)]);
+        assert!(meshes.removed.is_empty());
+        assert_eq!(meshes.stats.cached, 0);
+
+        // The feature at (2048, 0) left the view, one at (0, 2048) entered
+        let mut second = builder(Some(meshes.cache));
+        process_square(&mut second, 0.0, 0.0);
+        process_square(&mut second, 0.0, 2048.0);
+        let meshes = second.into_meshes();
+        assert_eq!(meshes.stats.features, 2);
+        assert_eq!(meshes.stats.cached, 1);
+        assert_eq!(meshes.stats.polygons, 1);
+        assert_eq!(cells(&meshes.chunks), vec![(0, 2)]);
+        assert_eq!(meshes.removed, vec![(2, 0)]);
+        assert_eq!(meshes.cache.len(), 2);
+    }
+
+    #[test]
+    fn builder_keeps_identical_geometries() {
+        let mut first = builder(None);
+        process_square(&mut first, 0.0, 0.0);
+        process_square(&mut first, 0.0, 0.0);
+        let meshes = first.into_meshes();
+        assert_eq!(meshes.cache.len(), 2);
+
+        let mut second = builder(Some(meshes.cache));
+        process_square(&mut second, 0.0, 0.0);
+        process_square(&mut second, 0.0, 0.0);
+        let meshes = second.into_meshes();
+        assert_eq!(meshes.stats.cached, 2);
+        assert!(meshes.chunks.is_empty());
+        assert!(meshes.removed.is_empty());
+    }
+
+    #[test]
+    fn builder_drops_cache_of_other_transform() {
+        let mut first = builder(None);
+        process_square(&mut first, 0.0, 0.0);
+        let meshes = first.into_meshes();
+
+        let transform = MapTransform::new(MapCoord::new(0.0, 0.0), 2.0);
+        let mut second =
+            MeshBuilder::<Lyon>::new(&transform, &LayerStyle::default()).with_cache(meshes.cache);
+        process_square(&mut second, 0.0, 0.0);
+        let meshes = second.into_meshes();
+        assert_eq!(meshes.stats.cached, 0);
+        assert_eq!(cells(&meshes.chunks), vec![(0, 0)]);
+    }
+
+    /// Process a line feature from (x, y) to (x + 10, y + 5)
+    fn process_line<T: Triangulator>(builder: &mut MeshBuilder<T>, x: f64, y: f64) {
+        builder.linestring_begin(false, 2, 0).unwrap();
+        builder.xy(x, y, 0).unwrap();
+        builder.xy(x + 10.0, y + 5.0, 1).unwrap();
+        builder.linestring_end(false, 0).unwrap();
+        builder.feature_end();
+    }
+
+    /// Check that the triangles of every feature only index its own vertices
+    /// and that optional attributes have one value per vertex
+    fn check_feature_ranges(buffers: &MeshBuffers) -> usize {
+        let num_vertices = buffers.vertices.len();
+        assert_eq!(buffers.feature_ids.len(), num_vertices);
+        assert_eq!(buffers.extrusions.len(), num_vertices);
+        // Vertex range of each feature
+        let mut ranges: HashMap<u32, (usize, usize)> = HashMap::default();
+        for (idx, id) in buffers.feature_ids.iter().enumerate() {
+            let range = ranges.entry(*id).or_insert((idx, idx));
+            // Vertices of a feature are contiguous
+            assert!(range.1 == idx || range.1 + 1 == idx);
+            range.1 = idx;
+        }
+        for triangle in buffers.triangles.chunks(3) {
+            let id = buffers.feature_ids[triangle[0] as usize];
+            let (first, last) = ranges[&id];
+            for idx in triangle {
+                assert!((first..=last).contains(&(*idx as usize)));
+            }
+        }
+        ranges.len()
+    }
+
+    #[test]
+    fn parallel_tessellation_index_bases() {
+        let transform = MapTransform::new(MapCoord::new(0.0, 0.0), 1.0);
+        let style = LayerStyle {
+            outline: Some(Outline {
+                color: Color::BLACK,
+                width: 1.0,
+            }),
+            ..Default::default()
+        };
+        let mut builder =
+            MeshBuilder::<Lyon>::new(&transform, &style).with_task_pool(TaskPool::new());
+        // Polygons and lines alternate in the fill mesh
+        let num_features = 2 * BATCH_SIZE + 10;
+        for i in 0..num_features {
+            let (x, y) = ((i % 32) as f64 * 20.0, (i / 32) as f64 * 20.0);
+            if i % 2 == 0 {
+                process_square(&mut builder, x, y);
+            } else {
+                process_line(&mut builder, x, y);
+            }
+        }
+        builder.tessellate_pending();
+        assert_eq!(builder.stats.features, num_features);
+        assert_eq!(builder.stats.polygons, num_features / 2);
+        assert_eq!(builder.stats.lines, num_features / 2);
+        assert_eq!(builder.stats.line_failed + builder.stats.stroke_failed, 0);
+
+        let (buffers, removed) = builder.cache.merge_buffers();
+        assert!(removed.is_empty());
+        let (fill, outline) = &buffers[&(0, 0)];
+        assert_eq!(check_feature_ranges(fill), num_features);
+        assert_eq!(check_feature_ranges(outline), num_features / 2);
+    }
+}
@@ -0,0 +1,481 @@
+//! Mapbox Vector Tile (MVT) layers
+//!
+//! Tiles are decoded with the messages of the vector tile specification 2.1.
+//! Polygons are clipped to the tile extent plus a buffer by tile generators. Outline
+//! segments outside the tile extent, like the clip edges, aren't stroked. Fills of
+//! neighbouring tiles overlap in the buffer, which shows with transparent fill colors.
+use crate::crs::Crs;
+use crate::label::Label;
+use crate::layer::{FgbLayer, VectorTiles};
+use crate::loader::{LayerFeature, LayerReader};
+use crate::mesh_builder::{FeatureCache, MapMeshes, Triangulator};
+use crate::tiles::{tile_bbox, TileId, TileRange, TileReader, MAX_TILES};
+use crate::transform::{MapCoord, MapTransform};
+use bevy::prelude::*;
+use bevy::tasks::TaskPool;
+use flate2::read::GzDecoder;
+use geozero::error::Result;
+use geozero::{ColumnValue, GeomProcessor, PropertyProcessor};
+use prost::Message;
+use std::io::Read;
+use vector_tile::{tile, Tile};
+
+/// Messages of vector_tile.proto
+mod vector_tile {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Tile {
+        #[prost(message, repeated, tag = "3")]
+        pub layers: Vec<tile::Layer>,
+    }
+
+    pub mod tile {
+        #[derive(Clone, PartialEq, prost::Message)]
+        pub struct Value {
+            #[prost(string, optional, tag = "1")]
+            pub string_value: Option<String>,
+            #[prost(float, optional, tag = "2")]
+            pub float_value: Option<f32>,
+            #[prost(double, optional, tag = "3")]
+            pub double_value: Option<f64>,
+            #[prost(int64, optional, tag = "4")]
+            pub int_value: Option<i64>,
+            #[prost(uint64, optional, tag = "5")]
+            pub uint_value: Option<u64>,
+            #[prost(sint64, optional, tag = "6")]
+            pub sint_value: Option<i64>,
+            #[prost(bool, optional, tag = "7")]
+            pub bool_value: Option<bool>,
+        }
+
+        #[derive(Clone, PartialEq, prost::Message)]
+        pub struct Feature {
+            #[prost(uint64, optional, tag = "1")]
+            pub id: Option<u64>,
+            /// Pairs of key and value indices
+            #[prost(uint32, repeated, tag = "2")]
+            pub tags: Vec<u32>,
+            /// Geometry type (`GeomType` enumeration)
+            #[prost(int32, optional, tag = "3")]
+            pub r#type: Option<i32>,
+            /// Geometry commands
+            #[prost(uint32, repeated, tag = "4")]
+            pub geometry: Vec<u32>,
+        }
+
+        #[derive(Clone, PartialEq, prost::Message)]
+        pub struct Layer {
+            #[prost(uint32, required, tag = "15")]
+            pub version: u32,
+            #[prost(string, required, tag = "1")]
+            pub name: String,
+            #[prost(message, repeated, tag = "2")]
+            pub features: Vec<Feature>,
+            #[prost(string, repeated, tag = "3")]
+            pub keys: Vec<String>,
+            #[prost(message, repeated, tag = "4")]
+            pub values: Vec<Value>,
+            #[prost(uint32, optional, tag = "5")]
+            pub extent: Option<u32>,
+        }
+    }
+}
+
+const MOVE_TO: u32 = 1;
+const LINE_TO: u32 = 2;
+const CLOSE_PATH: u32 = 7;
+
+const POINT: i32 = 1;
+const LINESTRING: i32 = 2;
+const POLYGON: i32 = 3;
+
+/// Decode zigzag encoded parameter integer
+fn zigzag(value: u32) -> i32 {
+    ((value >> 1) as i32) ^ -((value & 1) as i32)
+}
+
+/// Decode geometry commands into points, lines or rings in tile coordinates
+fn decode_parts(geometry: &[u32]) -> Vec<Vec<[i32; 2]>> {
+    let mut parts: Vec<Vec<[i32; 2]>> = Vec::new();
+    let (mut x, mut y) = (0, 0);
+    let mut i = 0;
+    while i < geometry.len() {
+        let command = geometry[i] & 0x7;
+        let count = (geometry[i] >> 3) as usize;
+        i += 1;
+        match command {
+            MOVE_TO | LINE_TO => {
+                for _ in 0..count {
+                    if i + 2 > geometry.len() {
+                        return parts;
+                    }
+                    // Parameters are relative to the previous position
+                    x += zigzag(geometry[i]);
+                    y += zigzag(geometry[i + 1]);
+                    i += 2;
+                    if command == MOVE_TO {
+                        parts.push(Vec::new());
+                    }
+                    if let Some(part) = parts.last_mut() {
+                        part.push([x, y]);
+                    }
+                }
+            }
+            CLOSE_PATH => {
+                if let Some(part) = parts.last_mut() {
+                    if let Some(first) = part.first().copied() {
+                        part.push(first);
+                    }
+                }
+            }
+            _ => break,
+        }
+    }
+    parts
+}
+
+/// Signed ring area in tile coordinates (positive for exterior rings)
+fn ring_area(ring: &[[i32; 2]]) -> i64 {
+    ring.windows(2)
+        .map(|segment| {
+            let ([x0, y0], [x1, y1]) = (segment[0], segment[1]);
+            x0 as i64 * y1 as i64 - x1 as i64 * y0 as i64
+        })
+        .sum()
+}
+
+/// Transformation from tile coordinates to map coordinates
+struct TileTransform {
+    /// Web mercator extent of the tile
+    bbox: (f64, f64, f64, f64),
+    /// Tile coordinate extent
+    extent: f64,
+    crs: Crs,
+}
+
+impl TileTransform {
+    /// Map extent of the tile without its buffer
+    fn map_bbox(&self) -> (f64, f64, f64, f64) {
+        let extent = self.extent as i32;
+        let corners = [[0, 0], [extent, 0], [0, extent], [extent, extent]];
+        let init = (f64::MAX, f64::MAX, f64::MIN, f64::MIN);
+        corners.iter().fold(init, |bbox, corner| {
+            let coord = self.to_map(*corner);
+            (
+                bbox.0.min(coord.x),
+                bbox.1.min(coord.y),
+                bbox.2.max(coord.x),
+                bbox.3.max(coord.y),
+            )
+        })
+    }
+
+    fn to_map(&self, [x, y]: [i32; 2]) -> MapCoord {
+        let (min_x, min_y, max_x, max_y) = self.bbox;
+        // Tile y axis points down
+        let mercator = MapCoord::new(
+            min_x + x as f64 / self.extent * (max_x - min_x),
+            max_y - y as f64 / self.extent * (max_y - min_y),
+        );
+        Crs::WEB_MERCATOR
+            .transform_to(mercator, self.crs)
+            .unwrap_or(mercator)
+    }
+
+    fn process_line<P: GeomProcessor>(
+        &self,
+        line: &[[i32; 2]],
+        tagged: bool,
+        idx: usize,
+        processor: &mut P,
+    ) -> Result<()> {
+        processor.linestring_begin(tagged, line.len(), idx)?;
+        for (i, pos) in line.iter().enumerate() {
+            let coord = self.to_map(*pos);
+            processor.xy(coord.x, coord.y, i)?;
+        }
+        processor.linestring_end(tagged, idx)
+    }
+
+    /// Process the geometry of a feature in map coordinates
+    fn process_geometry<P: GeomProcessor>(
+        &self,
+        feature: &tile::Feature,
+        processor: &mut P,
+    ) -> Result<()> {
+        let parts = decode_parts(&feature.geometry);
+        match feature.r#type.unwrap_or(0) {
+            POINT => {
+                processor.multipoint_begin(parts.len(), 0)?;
+                for (i, pos) in parts.iter().flatten().enumerate() {
+                    let coord = self.to_map(*pos);
+                    processor.xy(coord.x, coord.y, i)?;
+                }
+                processor.multipoint_end(0)
+            }
+            LINESTRING => {
+                processor.multilinestring_begin(parts.len(), 0)?;
+                for (i, line) in parts.iter().enumerate() {
+                    self.process_line(line, false, i, processor)?;
+                }
+                processor.multilinestring_end(0)
+            }
+            POLYGON => {
+                // Each exterior ring starts a new polygon
+                let mut polygons: Vec<Vec<&Vec<[i32; 2]>>> = Vec::new();
+                for ring in &parts {
+                    if ring_area(ring) > 0 || polygons.is_empty() {
+                        polygons.push(vec![ring]);
+                    } else if let Some(polygon) = polygons.last_mut() {
+                        polygon.push(ring);
+                    }
+                }
+                processor.multipolygon_begin(polygons.len(), 0)?;
+                for (i, rings) in polygons.iter().enumerate() {
+                    processor.polygon_begin(false, rings.len(), i)?;
+                    for (j, ring) in rings.iter().enumerate() {
+                        self.process_line(ring, false, j, processor)?;
+                    }
+                    processor.polygon_end(false, i)?;
+                }
+                processor.multipolygon_end(0)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Attribute value of a feature
+fn column_value(value: &tile::Value) -> Option<ColumnValue> {
+    if let Some(v) = &value.string_value {
+        Some(ColumnValue::String(v))
+    } else if let Some(v) = value.float_value {
+        Some(ColumnValue::Float(v))
+    } else if let Some(v) = value.double_value {
+        Some(ColumnValue::Double(v))
+    } else if let Some(v) = value.int_value.or(value.sint_value) {
+        Some(ColumnValue::Long(v))
+    } else if let Some(v) = value.uint_value {
+        Some(ColumnValue::ULong(v))
+    } else {
+        value.bool_value.map(ColumnValue::Bool)
+    }
+}
+
+/// Process the attributes of a feature
+fn process_properties<P: PropertyProcessor>(
+    layer: &tile::Layer,
+    feature: &tile::Feature,
+    processor: &mut P,
+) -> Result<()> {
+    for (idx, tag) in feature.tags.chunks_exact(2).enumerate() {
+        let key = layer.keys.get(tag[0] as usize);
+        let value = layer.values.get(tag[1] as usize).and_then(column_value);
+        if let (Some(key), Some(value)) = (key, value) {
+            if processor.property(idx, key, &value)? {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Read and decode a tile. Tiles may be gzip compressed.
+fn read_tile(reader: &TileReader, id: TileId) -> Option<Tile> {
+    let data = reader.read(id)?;
+    let data = if data.starts_with(&[0x1f, 0x8b]) {
+        let mut decompressed = Vec::new();
+        if let Err(err) = GzDecoder::new(&data[..]).read_to_end(&mut decompressed) {
+            warn!("Decompressing tile {:?} failed: {}", id, err);
+            return None;
+        }
+        decompressed
+    } else {
+        data
+    };
+    match Tile::decode(&data[..]) {
+        Ok(tile) => Some(tile),
+        Err(err) => {
+            warn!("Decoding tile {:?} failed: {}", id, err);
+            None
+        }
+    }
+}
+
+/// Feature of a tile layer
+struct TileFeature<'a> {
+    layer: &'a tile::Layer,
+    feature: &'a tile::Feature,
+    transform: &'a TileTransform,
+}
+
+impl LayerFeature for TileFeature<'_> {
+    fn process_geometry<P: GeomProcessor>(&self, processor: &mut P) -> Result<()> {
+        self.transform.process_geometry(self.feature, processor)
+    }
+
+    fn process_properties<P: PropertyProcessor>(&self, processor: &mut P) -> Result<()> {
+        process_properties(self.layer, self.feature, processor)
+    }
+}
+
+/// Read and tessellate the features of the vector tiles covering `bbox`
+/// and collect their labels.
+/// Features crossing tile boundaries are tessellated per tile.
+pub fn read_mvt<T: Triangulator>(
+    layer: &FgbLayer,
+    tiles: &VectorTiles,
+    bbox: (f64, f64, f64, f64),
+    crs: Crs,
+    transform: &MapTransform,
+    cache: Option<FeatureCache>,
+    pool: &TaskPool,
+) -> (MapMeshes, Vec<Label>) {
+    let span = info_span!("read_mvt");
+    let _read_mvt_span = span.enter();
+    let mut reader = LayerReader::<T>::new(layer, transform, cache).with_task_pool(pool.clone());
+
+    // Tile level of the current level of detail
+    let lod = (crs.resolution(0.0) / transform.resolution).log2().round();
+    let z = (lod.max(0.0) as u8).max(tiles.min_zoom).min(tiles.max_zoom);
+    let range = match TileRange::covering(bbox, crs, z) {
+        Some(range) if range.len() <= MAX_TILES => Some(range),
+        Some(_) => {
+            warn!("{}: too many tiles at zoom level {}", tiles.layer, z);
+            None
+        }
+        None => {
+            warn!("Vector tiles not supported for EPSG:{}", crs.epsg);
+            None
+        }
+    };
+    let tile_reader = range.as_ref().and_then(|_| TileReader::open(&tiles.source));
+    if let (Some(range), Some(tile_reader)) = (range, tile_reader) {
+        for id in range.iter() {
+            let tile = match read_tile(&tile_reader, id) {
+                Some(tile) => tile,
+                None => continue,
+            };
+            let mvt_layer = match tile.layers.iter().find(|l| l.name == tiles.layer) {
+                Some(mvt_layer) => mvt_layer,
+                None => continue,
+            };
+            let tile_transform = TileTransform {
+                bbox: tile_bbox(id),
+                extent: mvt_layer.extent.unwrap_or(4096) as f64,
+                crs,
+            };
+            // Don't outline the edges of polygons clipped to the tile
+            reader.set_outline_clip(Some(tile_transform.map_bbox()));
+            for feature in &mvt_layer.features {
+                reader
+                    .process_feature(&TileFeature {
+                        layer: mvt_layer,
+                        feature,
+                        transform: &tile_transform,
+                    })
+                    .unwrap();
+            }
+        }
+    }
+    reader.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zigzag_decoding() {
+        assert_eq!(zigzag(0), 0);
+        assert_eq!(zigzag(1), -1);
+        assert_eq!(zigzag(2), 1);
+        assert_eq!(zigzag(3), -2);
+        assert_eq!(zigzag(u32::MAX - 1), i32::MAX);
+        assert_eq!(zigzag(u32::MAX), i32::MIN);
+    }
+
+    #[test]
+    fn close_path() {
+        // Polygon example of the specification
+        let parts = decode_parts(&[9, 6, 12, 18, 10, 12, 24, 44, 15]);
+        assert_eq!(parts, vec![vec![[3, 6], [8, 12], [20, 34], [3, 6]]]);
+    }
+
+    #[test]
+    fn relative_positions() {
+        // Multi line example of the specification
+        let parts = decode_parts(&[9, 4, 4, 18, 0, 16, 16, 0, 9, 17, 17, 10, 4, 8]);
+        assert_eq!(
+            parts,
+            vec![vec![[2, 2], [2, 10], [10, 10]], vec![[1, 1], [3, 5]]]
+        );
+    }
+
+    #[test]
+    fn truncated_geometry() {
+        assert_eq!(decode_parts(&[9, 4]), Vec::<Vec<[i32; 2]>>::new());
+        assert_eq!(decode_parts(&[9, 4, 4, 18, 0]), vec![vec![[2, 2]]]);
+    }
+
+    /// Ring sizes of each processed polygon
+    #[derive(Default)]
+    struct PolygonRecorder {
+        polygons: Vec<Vec<usize>>,
+    }
+
+    impl GeomProcessor for PolygonRecorder {
+        fn polygon_begin(&mut self, _tagged: bool, _size: usize, _idx: usize) -> Result<()> {
+            self.polygons.push(Vec::new());
+            Ok(())
+        }
+        fn linestring_begin(&mut self, _tagged: bool, size: usize, _idx: usize) -> Result<()> {
+            if let Some(rings) = self.polygons.last_mut() {
+                rings.push(size);
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn ring_orientation() {
+        // Multi polygon example of the specification: a square and a square with a hole
+        let geometry = vec![
+            9, 0, 0, 26, 20, 0, 0, 20, 19, 0, 15, 9, 22, 2, 26, 18, 0, 0, 18, 17, 0, 15, 9, 4, 13,
+            26, 0, 8, 8, 0, 0, 7, 15,
+        ];
+        let parts = decode_parts(&geometry);
+        assert_eq!(parts.len(), 3);
+        // Exterior rings are clockwise in tile coordinates (y down)
+        assert!(ring_area(&parts[0]) > 0);
+        assert!(ring_area(&parts[1]) > 0);
+        assert!(ring_area(&parts[2]) < 0);
+
+        let feature = tile::Feature {
+            r#type: Some(POLYGON),
+            geometry,
+            ..Default::default()
+        };
+        let tile_transform = TileTransform {
+            bbox: (0.0, 0.0, 4096.0, 4096.0),
+            extent: 4096.0,
+            crs: Crs::WEB_MERCATOR,
+        };
+        let mut recorder = PolygonRecorder::default();
+        tile_transform
+            .process_geometry(&feature, &mut recorder)
+            .unwrap();
+        assert_eq!(recorder.polygons, vec![vec![5], vec![5, 5]]);
+    }
+
+    #[test]
+    fn tile_map_bbox() {
+        let tile_transform = TileTransform {
+            bbox: (100.0, 200.0, 300.0, 400.0),
+            extent: 4096.0,
+            crs: Crs::WEB_MERCATOR,
+        };
+        assert_eq!(tile_transform.map_bbox(), (100.0, 200.0, 300.0, 400.0));
+        // Tile y axis points down
+        assert_eq!(tile_transform.to_map([0, 0]), MapCoord::new(100.0, 400.0));
+    }
+}
@@ -1,17 +1,27 @@
 use crate::instant::Instant;
+use crate::map::{Map, ZoomLevels};
+use crate::transform::rotate;
+#[cfg(target_arch = "wasm32")]
+use crate::web_touch;
 use bevy::input::mouse::{MouseMotion, MouseWheel};
+use bevy::input::touch::Touches;
 use bevy::prelude::*;
+use bevy::utils::HashSet;
 
-/// Tags an entity as capable of panning and orbiting.
+/// Tags an entity as capable of panning, zooming and rotating.
 pub struct PanOrbitCamera {
-    /// The "focus point" to orbit around. It is automatically updated when panning the camera
+    /// The "focus point" to rotate around. It is automatically updated when panning the camera
     pub focus: Vec3,
+    /// Displayed zoom level, including zooming between map reloads.
+    /// The camera scale follows this level, the camera height stays fixed.
+    pub zoom: f32,
 }
 
 impl Default for PanOrbitCamera {
     fn default() -> Self {
         PanOrbitCamera {
             focus: Vec3::zero(),
+            zoom: 0.0,
         }
     }
 }
@@ -26,39 +36,181 @@ pub struct InputState {
     pub cursor_startpos: Option<Vec2>,
     // Timestamp when motions begins
     pub last_motion: Option<Instant>,
+    // Current cursor position
+    pub reader_cursor_position: EventReader<CursorMoved>,
+    pub cursor_position: Option<Vec2>,
     pub reader_scroll: EventReader<MouseWheel>,
     // Timestamp when scroll begins
     pub last_zoom: Option<Instant>,
+    // Cursor position when scrolling, kept fixed while zooming
+    pub zoom_anchor: Option<Vec2>,
+    // Reset view requested
+    pub reset_view: bool,
+    pub reader_gamepad: EventReader<GamepadEvent>,
+    // Connected gamepads
+    pub gamepads: HashSet<Gamepad>,
+}
+
+/// Keyboard and gamepad bindings for camera navigation
+pub struct InputBindings {
+    pub pan_left: Vec<KeyCode>,
+    pub pan_right: Vec<KeyCode>,
+    pub pan_up: Vec<KeyCode>,
+    pub pan_down: Vec<KeyCode>,
+    pub zoom_in: Vec<KeyCode>,
+    pub zoom_out: Vec<KeyCode>,
+    /// Rotate the map counter-clockwise
+    pub rotate_left: Vec<KeyCode>,
+    /// Rotate the map clockwise
+    pub rotate_right: Vec<KeyCode>,
+    pub reset: Vec<KeyCode>,
+    /// Horizontal and vertical pan axes
+    pub gamepad_pan: (GamepadAxisType, GamepadAxisType),
+    /// Rotation axis (positive values rotate the map clockwise)
+    pub gamepad_rotate: GamepadAxisType,
+    pub gamepad_zoom_in: GamepadButtonType,
+    pub gamepad_zoom_out: GamepadButtonType,
+    pub gamepad_reset: GamepadButtonType,
+    /// Axis values below are ignored
+    pub gamepad_dead_zone: f32,
+    /// Pan speed in pixels per second
+    pub pan_speed: f32,
+    /// Zoom speed in scroll units per second
+    pub zoom_speed: f32,
+    /// Rotation speed in radians per second
+    pub rotate_speed: f32,
+}
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        InputBindings {
+            pan_left: vec![KeyCode::Left, KeyCode::A],
+            pan_right: vec![KeyCode::Right, KeyCode::D],
+            pan_up: vec![KeyCode::Up, KeyCode::W],
+            pan_down: vec![KeyCode::Down, KeyCode::S],
+            zoom_in: vec![KeyCode::Add, KeyCode::Equals],
+            zoom_out: vec![KeyCode::Subtract, KeyCode::Minus],
+            rotate_left: vec![KeyCode::Q],
+            rotate_right: vec![KeyCode::E],
+            reset: vec![KeyCode::Home],
+            gamepad_pan: (GamepadAxisType::LeftStickX, GamepadAxisType::LeftStickY),
+            gamepad_rotate: GamepadAxisType::RightStickX,
+            gamepad_zoom_in: GamepadButtonType::RightTrigger2,
+            gamepad_zoom_out: GamepadButtonType::LeftTrigger2,
+            gamepad_reset: GamepadButtonType::Select,
+            gamepad_dead_zone: 0.15,
+            pan_speed: 400.0,
+            zoom_speed: 10.0,
+            rotate_speed: 1.0,
+        }
+    }
+}
+
+impl InputBindings {
+    fn axis(&self, keys: &Input<KeyCode>, negative: &[KeyCode], positive: &[KeyCode]) -> f32 {
+        let pressed = |codes: &[KeyCode]| codes.iter().any(|code| keys.pressed(*code));
+        match (pressed(negative), pressed(positive)) {
+            (true, false) => -1.0,
+            (false, true) => 1.0,
+            _ => 0.0,
+        }
+    }
+
+    fn gamepad_axis(
+        &self,
+        axes: &Axis<GamepadAxis>,
+        gamepad: Gamepad,
+        axis: GamepadAxisType,
+    ) -> f32 {
+        let value = axes.get(GamepadAxis(gamepad, axis)).unwrap_or(0.0);
+        if value.abs() < self.gamepad_dead_zone {
+            0.0
+        } else {
+            value
+        }
+    }
 }
 
 const PAN_FACTOR: f32 = 100.0;
 const PAN_FACTOR_WEB: f32 = 2.0;
 
-/// Pan the camera with LHold or scrollwheel, orbit with rclick.
+/// Scroll lines per zoom level
+const SCROLL_PER_LEVEL: f32 = 4.0;
+
+/// Two-finger gesture between the previous and the current touch positions
+struct TouchGesture {
+    /// Distance ratio (> 1 when spreading fingers)
+    pinch: f32,
+    /// Rotation angle in radians (counter-clockwise on screen)
+    rotation: f32,
+    /// Center between the fingers in screen coordinates
+    center: Vec2,
+}
+
+impl TouchGesture {
+    fn new(prev: (Vec2, Vec2), curr: (Vec2, Vec2)) -> Option<Self> {
+        let prev_v = prev.1 - prev.0;
+        let curr_v = curr.1 - curr.0;
+        if prev_v.length() < 1.0 || curr_v.length() < 1.0 {
+            return None;
+        }
+        // Touch positions have their origin at the top left
+        let rotation = -(curr_v.y.atan2(curr_v.x) - prev_v.y.atan2(prev_v.x));
+        Some(TouchGesture {
+            pinch: curr_v.length() / prev_v.length(),
+            rotation,
+            center: (curr.0 + curr.1) / 2.0,
+        })
+    }
+}
+
+/// Minimal rotation in radians to rotate instead of zooming
+const TOUCH_ROTATE_THRESHOLD: f32 = 0.02;
+
+/// Pan the camera with LHold, zoom with the scrollwheel, rotate with rclick.
+/// The map is always viewed from above: the camera only rotates around the view axis.
+/// Keyboard and gamepad navigation according to `InputBindings`.
+#[allow(clippy::too_many_arguments)]
 fn pan_orbit_camera(
     time: Res<Time>,
     windows: Res<Windows>,
+    bindings: Res<InputBindings>,
     mut state: ResMut<InputState>,
     ev_motion: Res<Events<MouseMotion>>,
     ev_cursor: Res<Events<CursorMoved>>,
     mousebtn: Res<Input<MouseButton>>,
     ev_scroll: Res<Events<MouseWheel>>,
+    keys: Res<Input<KeyCode>>,
+    ev_gamepad: Res<Events<GamepadEvent>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    touches: Res<Touches>,
+    zoom_levels: Res<ZoomLevels>,
+    mut map: ResMut<Map>,
     mut query: Query<(&mut PanOrbitCamera, &mut Transform)>,
 ) {
     let mut translation = Vec2::zero();
-    let mut rotation_move = Vec2::default();
+    // Horizontal mouse motion while rotating
+    let mut rotation_move = 0.0;
     let mut scroll = 0.0;
+    let mut pinch = 1.0;
     let dt = time.delta_seconds;
+    // Web: touches are also reported as mouse input
+    let touching = touches.iter().next().is_some();
 
-    if mousebtn.pressed(MouseButton::Right) {
+    if touching {
+        // Skip mouse events of the touches
+        for _ in state.reader_motion.iter(&ev_motion) {}
+        for _ in state.reader_cursor.iter(&ev_cursor) {}
+        state.cursor_startpos = None;
+    } else if mousebtn.pressed(MouseButton::Right) {
         for ev in state.reader_motion.iter(&ev_motion) {
-            rotation_move += ev.delta;
+            rotation_move += ev.delta.x;
         }
         // Web: absolute position instead of delta
         for ev in state.reader_cursor.iter(&ev_cursor) {
             if let Some(startpos) = state.cursor_startpos {
-                rotation_move.x = (ev.position.x - startpos.x) * PAN_FACTOR_WEB;
-                rotation_move.y = (startpos.y - ev.position.y) * PAN_FACTOR_WEB;
+                rotation_move = (ev.position.x - startpos.x) * PAN_FACTOR_WEB;
             } else {
                 state.cursor_startpos = Some(ev.position);
             }
@@ -82,39 +234,139 @@ fn pan_orbit_camera(
         state.cursor_startpos = None;
     }
 
+    let cursor_position = state
+        .reader_cursor_position
+        .iter(&ev_cursor)
+        .last()
+        .map(|ev| ev.position);
+    if cursor_position.is_some() {
+        state.cursor_position = cursor_position;
+    }
+
     for ev in state.reader_scroll.iter(&ev_scroll) {
         scroll += ev.y;
     }
+    if scroll != 0.0 {
+        state.zoom_anchor = state.cursor_position;
+    }
 
-    // Either pan+scroll or arcball. We don't do both at once.
-    for (mut camera, mut trans) in query.iter_mut() {
-        if rotation_move.length_squared() > 0.0 {
-            let window = windows.get_primary().unwrap();
-            let window_w = window.width() as f32;
-            let window_h = window.height() as f32;
-
-            // Link virtual sphere rotation relative to window to make it feel nicer
-            let delta_x = rotation_move.x / window_w * std::f32::consts::PI * 2.0;
-            let delta_y = rotation_move.y / window_h * std::f32::consts::PI;
-
-            let delta_yaw = Quat::from_rotation_y(delta_x);
-            let delta_pitch = Quat::from_rotation_x(delta_y);
-
-            trans.translation =
-                delta_yaw * delta_pitch * (trans.translation - camera.focus) + camera.focus;
+    // Keyboard and gamepad navigation
+    for ev in state.reader_gamepad.iter(&ev_gamepad) {
+        match ev.1 {
+            GamepadEventType::Connected => {
+                state.gamepads.insert(ev.0);
+            }
+            GamepadEventType::Disconnected => {
+                state.gamepads.remove(&ev.0);
+            }
+            _ => {}
+        }
+    }
+    let mut pan = Vec2::new(
+        bindings.axis(&keys, &bindings.pan_left, &bindings.pan_right),
+        bindings.axis(&keys, &bindings.pan_down, &bindings.pan_up),
+    );
+    let mut zoom = bindings.axis(&keys, &bindings.zoom_out, &bindings.zoom_in);
+    let mut turn = bindings.axis(&keys, &bindings.rotate_left, &bindings.rotate_right);
+    let mut reset = bindings.reset.iter().any(|code| keys.just_pressed(*code));
+    for gamepad in state.gamepads.iter() {
+        let (axis_x, axis_y) = bindings.gamepad_pan;
+        pan.x += bindings.gamepad_axis(&gamepad_axes, *gamepad, axis_x);
+        pan.y += bindings.gamepad_axis(&gamepad_axes, *gamepad, axis_y);
+        turn += bindings.gamepad_axis(&gamepad_axes, *gamepad, bindings.gamepad_rotate);
+        if gamepad_buttons.pressed(GamepadButton(*gamepad, bindings.gamepad_zoom_in)) {
+            zoom += 1.0;
+        }
+        if gamepad_buttons.pressed(GamepadButton(*gamepad, bindings.gamepad_zoom_out)) {
+            zoom -= 1.0;
+        }
+        reset |= gamepad_buttons.just_pressed(GamepadButton(*gamepad, bindings.gamepad_reset));
+    }
+    if pan != Vec2::zero() {
+        // Same direction convention as mouse motion
+        translation += Vec2::new(-pan.x, pan.y) * bindings.pan_speed;
+        state.last_motion = Some(Instant::now());
+    }
+    if zoom != 0.0 {
+        scroll += zoom * bindings.zoom_speed * dt;
+        // Zoom towards the window center
+        state.zoom_anchor = windows
+            .get_primary()
+            .map(|window| Vec2::new(window.width() as f32, window.height() as f32) / 2.0);
+    }
+    // Rotation around the view axis. Rotating the camera counter-clockwise turns the
+    // map clockwise on screen.
+    let mut view_rotation = turn * bindings.rotate_speed * dt;
+    if rotation_move != 0.0 {
+        if let Some(window) = windows.get_primary() {
+            // A drag across the window turns the map once
+            view_rotation += rotation_move / window.width() as f32 * std::f32::consts::PI * 2.0;
+        }
+    }
+    if reset {
+        state.reset_view = true;
+    }
 
-            let look = Mat4::face_toward(trans.translation, camera.focus, Vec3::new(0.0, 1.0, 0.0));
-            trans.rotation = look.to_scale_rotation_translation().1;
-        } else {
-            // The plane is x/y while z is "up". Multiplying by dt allows for a constant pan rate
-            let mut translation = Vec3::new(-translation.x * dt, translation.y * dt, 0.0);
-            camera.focus += translation;
-            translation.z = -scroll;
-            trans.translation += translation;
-            if scroll != 0.0 {
-                state.last_zoom = Some(Instant::now());
+    // Touch gestures
+    let active_touches: Vec<_> = touches.iter().collect();
+    match active_touches.as_slice() {
+        [touch] => {
+            // One-finger pan, following the finger like mouse motion
+            let delta = touch.position() - touch.previous_position();
+            if delta != Vec2::zero() {
+                translation += delta / dt.max(0.001);
+                state.last_motion = Some(Instant::now());
             }
         }
+        [touch1, touch2] => {
+            let gesture = TouchGesture::new(
+                (touch1.previous_position(), touch2.previous_position()),
+                (touch1.position(), touch2.position()),
+            );
+            if let (Some(gesture), Some(window)) = (gesture, windows.get_primary()) {
+                let window_h = window.height() as f32;
+                if gesture.rotation.abs() > TOUCH_ROTATE_THRESHOLD {
+                    // Two-finger rotate. The map follows the fingers.
+                    view_rotation -= gesture.rotation;
+                } else if (gesture.pinch - 1.0).abs() > f32::EPSILON {
+                    pinch = gesture.pinch;
+                    // Screen coordinates have their origin at the bottom left
+                    state.zoom_anchor =
+                        Some(Vec2::new(gesture.center.x, window_h - gesture.center.y));
+                }
+            }
+        }
+        _ => {}
+    }
+    if view_rotation != 0.0 {
+        map.rotation += view_rotation;
+        // Check the loaded area after rotating
+        state.last_motion = Some(Instant::now());
+    }
+
+    // Resetting the view is handled by `pan_or_zoom`.
+    for (mut camera, mut trans) in query.iter_mut() {
+        if view_rotation != 0.0 {
+            // Rotate around the view axis through the focus point
+            trans.rotation = Quat::from_rotation_z(view_rotation) * trans.rotation;
+        }
+        // The plane is x/y while z is "up". Multiplying by dt allows for a constant pan rate
+        // Screen pixels are converted to world units with the camera scale and rotation
+        let translation = (rotate(
+            Vec2::new(-translation.x * dt, translation.y * dt),
+            map.rotation,
+        ) * trans.scale.x)
+            .extend(0.0);
+        camera.focus += translation;
+        trans.translation += translation;
+        // Stay within the zoom level limits
+        let zoom = (camera.zoom + scroll / SCROLL_PER_LEVEL + pinch.log2())
+            .max(zoom_levels.min)
+            .min(zoom_levels.max);
+        if zoom != camera.zoom {
+            camera.zoom = zoom;
+            state.last_zoom = Some(Instant::now());
+        }
     }
 }
 
@@ -129,7 +381,16 @@ pub struct PanOrbitCameraPlugin;
 impl Plugin for PanOrbitCameraPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.add_resource(InputState::default())
-            .add_system(spawn_camera2d.system())
+            .init_resource::<InputBindings>()
+            .add_startup_system(spawn_camera2d.system())
             .add_system(pan_orbit_camera.system());
+
+        #[cfg(target_arch = "wasm32")]
+        app.init_resource::<web_touch::TouchQueue>()
+            .add_startup_system(web_touch::setup_touch_events.system())
+            .add_system_to_stage(
+                bevy::app::stage::FIRST,
+                web_touch::send_touch_events.system(),
+            );
     }
 }
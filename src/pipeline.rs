@@ -0,0 +1,157 @@
+//! Render pipeline for map meshes
+//!
+//! Map meshes only have 2D positions, with optional per-vertex colors, feature ids and
+//! stroke extrusions.
+//! The shader declares the optional attributes only for meshes having them, as
+//! selected by the `MapMeshAttributes` component.
+use crate::pan_orbit_camera::PanOrbitCamera;
+use bevy::prelude::*;
+use bevy::render::{
+    pipeline::{CullMode, PipelineDescriptor, RenderPipeline},
+    render_graph::{base, AssetRenderResourcesNode, RenderGraph},
+    renderer::RenderResources,
+    shader::{shader_defs_system, ShaderDefs, ShaderStage, ShaderStages},
+};
+use bevy::type_registry::TypeUuid;
+
+/// Per-vertex color (`[f32; 4]`). Overrides the material color.
+pub const ATTRIBUTE_COLOR: &str = "Vertex_Color";
+/// Per-vertex feature id (`u32`)
+pub const ATTRIBUTE_FEATURE_ID: &str = "Vertex_FeatureId";
+/// Per-vertex stroke extrusion in pixels (`[f32; 2]`), scaled with the camera scale
+pub const ATTRIBUTE_EXTRUSION: &str = "Vertex_Extrusion";
+
+pub const MAP_PIPELINE_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(PipelineDescriptor::TYPE_UUID, 8_317_421_645_263_114_207);
+
+/// Material of map meshes
+#[derive(RenderResources, TypeUuid)]
+#[uuid = "5d6c8b4e-2f7a-4c1e-9a3b-7e0f1d2c3b4a"]
+pub struct MapMaterial {
+    pub color: Color,
+    /// World units per pixel for stroke extrusions. Follows the camera scale.
+    pub camera_scale: f32,
+}
+
+impl Default for MapMaterial {
+    fn default() -> Self {
+        Color::default().into()
+    }
+}
+
+impl From<Color> for MapMaterial {
+    fn from(color: Color) -> Self {
+        MapMaterial {
+            color,
+            camera_scale: 1.0,
+        }
+    }
+}
+
+/// Optional vertex attributes of a map mesh entity.
+/// Selects the shader variant declaring these attributes.
+#[derive(Clone, Copy, Debug, Default, ShaderDefs)]
+pub struct MapMeshAttributes {
+    #[shader_def]
+    pub vertex_color: bool,
+    #[shader_def]
+    pub vertex_feature_id: bool,
+    #[shader_def]
+    pub vertex_extrusion: bool,
+}
+
+impl MapMeshAttributes {
+    /// Optional attributes set in `mesh`
+    pub fn of(mesh: &Mesh) -> Self {
+        MapMeshAttributes {
+            vertex_color: mesh.attribute(ATTRIBUTE_COLOR).is_some(),
+            vertex_feature_id: mesh.attribute(ATTRIBUTE_FEATURE_ID).is_some(),
+            vertex_extrusion: mesh.attribute(ATTRIBUTE_EXTRUSION).is_some(),
+        }
+    }
+}
+
+mod node {
+    pub const MAP_MATERIAL: &str = "map_material";
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+const SHADERS: (&str, &str) = (
+    include_str!("shaders/map.vert"),
+    include_str!("shaders/map.frag"),
+);
+
+#[cfg(target_arch = "wasm32")]
+const SHADERS: (&str, &str) = (
+    include_str!("shaders/map_webgl2.vert"),
+    include_str!("shaders/map_webgl2.frag"),
+);
+
+fn build_map_pipeline(shaders: &mut Assets<Shader>) -> PipelineDescriptor {
+    let mut descriptor = PipelineDescriptor::default_config(ShaderStages {
+        vertex: shaders.add(Shader::from_glsl(ShaderStage::Vertex, SHADERS.0)),
+        fragment: Some(shaders.add(Shader::from_glsl(ShaderStage::Fragment, SHADERS.1))),
+    });
+    // Triangulation backends don't guarantee a winding order
+    if let Some(rasterization_state) = descriptor.rasterization_state.as_mut() {
+        rasterization_state.cull_mode = CullMode::None;
+    }
+    descriptor
+}
+
+/// Mesh entity drawn with the map pipeline.
+/// Spawn with the `MapMeshAttributes` of the mesh.
+pub fn map_mesh_bundle(mesh: Handle<Mesh>, transform: Transform) -> MeshBundle {
+    MeshBundle {
+        mesh,
+        render_pipelines: RenderPipelines::from_pipelines(vec![RenderPipeline::new(
+            MAP_PIPELINE_HANDLE.typed(),
+        )]),
+        transform,
+        ..Default::default()
+    }
+}
+
+/// Keep stroke widths in pixels while zooming with the camera scale
+pub(crate) fn update_camera_scale(
+    cameras: Query<&Transform, With<PanOrbitCamera>>,
+    mut materials: ResMut<Assets<MapMaterial>>,
+) {
+    let scale = match cameras.iter().next() {
+        Some(transform) => transform.scale.x,
+        None => return,
+    };
+    // Only modified materials are uploaded again
+    let outdated: Vec<_> = materials
+        .iter()
+        .filter(|(_, material)| material.camera_scale != scale)
+        .map(|(id, _)| id)
+        .collect();
+    for id in outdated {
+        if let Some(material) = materials.get_mut(id) {
+            material.camera_scale = scale;
+        }
+    }
+}
+
+/// Register map material and pipeline
+pub(crate) fn add_map_pipeline(app: &mut AppBuilder) {
+    app.add_asset::<MapMaterial>()
+        .add_system(update_camera_scale.system())
+        .add_system_to_stage(
+            bevy::app::stage::POST_UPDATE,
+            shader_defs_system::<MapMeshAttributes>.system(),
+        );
+    let resources = app.resources_mut();
+    let mut shaders = resources.get_mut::<Assets<Shader>>().unwrap();
+    let mut pipelines = resources.get_mut::<Assets<PipelineDescriptor>>().unwrap();
+    pipelines.set_untracked(MAP_PIPELINE_HANDLE, build_map_pipeline(&mut shaders));
+    let mut render_graph = resources.get_mut::<RenderGraph>().unwrap();
+    render_graph.add_system_node(
+        node::MAP_MATERIAL,
+        AssetRenderResourcesNode::<MapMaterial>::new(true),
+    );
+    render_graph
+        .add_node_edge(node::MAP_MATERIAL, base::node::MAIN_PASS)
+        .unwrap();
+}
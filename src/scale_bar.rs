@@ -0,0 +1,146 @@
+//! Scale bar and scale denominator display
+use crate::map::Map;
+use crate::pan_orbit_camera::PanOrbitCamera;
+use crate::transform::MapTransform;
+use crate::ui::{FONT, FONT_SIZE};
+use bevy::prelude::*;
+
+/// Maximal scale bar width in pixels
+const MAX_BAR_WIDTH: f64 = 120.0;
+
+/// Standardized rendering pixel size in meters (OGC SLD/SE)
+const PIXEL_SIZE: f64 = 0.00028;
+
+/// Tags the scale bar node
+pub struct ScaleBar;
+
+/// Tags the scale text
+pub struct ScaleText;
+
+pub(crate) fn setup_scale_bar(
+    commands: &mut Commands,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(10.0),
+                    bottom: Val::Px(10.0),
+                    ..Default::default()
+                },
+                size: Size::new(Val::Px(0.0), Val::Px(4.0)),
+                ..Default::default()
+            },
+            material: materials.add(Color::BLACK.into()),
+            ..Default::default()
+        })
+        .with(ScaleBar)
+        .spawn(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(10.0),
+                    bottom: Val::Px(16.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text {
+                value: String::new(),
+                font: asset_server.load(FONT),
+                style: TextStyle {
+                    font_size: FONT_SIZE,
+                    color: Color::BLACK,
+                    ..Default::default()
+                },
+            },
+            ..Default::default()
+        })
+        .with(ScaleText);
+}
+
+pub(crate) fn update_scale_bar(
+    windows: Res<Windows>,
+    map: Res<Map>,
+    cameras: Query<&Transform, With<PanOrbitCamera>>,
+    mut bars: Query<&mut Style, With<ScaleBar>>,
+    mut texts: Query<&mut Text, With<ScaleText>>,
+) {
+    let (window, camera) = match (windows.get_primary(), cameras.iter().next()) {
+        (Some(window), Some(camera)) => (window, camera),
+        _ => return,
+    };
+    let window_size = Vec2::new(window.width() as f32, window.height() as f32);
+    let transform = MapTransform::from_map(&map)
+        .with_camera(camera.translation.truncate(), camera.scale.x)
+        .with_window_size(window_size);
+    let center = transform.screen_to_map(window_size / 2.0);
+    // Meters per screen pixel
+    let ground_resolution = map.crs.ground_distance(transform.pixel_size(), center);
+    if ground_resolution.is_nan() || ground_resolution <= 0.0 {
+        return;
+    }
+    let distance = round_distance(MAX_BAR_WIDTH * ground_resolution);
+    let width = distance / ground_resolution;
+    for mut style in bars.iter_mut() {
+        style.size.width = Val::Px(width as f32);
+    }
+    let label = format!(
+        "{}  1:{:.0}",
+        format_distance(distance),
+        ground_resolution / PIXEL_SIZE
+    );
+    for mut text in texts.iter_mut() {
+        text.value = label.clone();
+    }
+}
+
+/// Largest round distance (1, 2 or 5 times a power of ten) not exceeding `max`
+fn round_distance(max: f64) -> f64 {
+    let magnitude = 10f64.powf(max.log10().floor());
+    [5.0, 2.0, 1.0]
+        .iter()
+        .map(|f| f * magnitude)
+        .find(|d| *d <= max)
+        .unwrap_or(magnitude)
+}
+
+fn format_distance(meters: f64) -> String {
+    if meters >= 1000.0 {
+        format!("{} km", meters / 1000.0)
+    } else if meters >= 1.0 {
+        format!("{} m", meters)
+    } else {
+        format!("{} cm", meters * 100.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_distance(max: f64, expected: f64) {
+        let distance = round_distance(max);
+        assert!(
+            (distance - expected).abs() < expected * 1e-9,
+            "round_distance({}) = {}",
+            max,
+            distance
+        );
+    }
+
+    #[test]
+    fn round_distances() {
+        assert_distance(1.0, 1.0);
+        assert_distance(3.0, 2.0);
+        assert_distance(7.5, 5.0);
+        assert_distance(19.0, 10.0);
+        assert_distance(250.0, 200.0);
+        assert_distance(999.0, 500.0);
+        assert_distance(5000.0, 5000.0);
+        assert_distance(0.3, 0.2);
+    }
+}
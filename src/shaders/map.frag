@@ -0,0 +1,23 @@
+#version 450
+
+#ifdef MAPMESHATTRIBUTES_VERTEX_COLOR
+layout(location = 0) in vec4 v_Color;
+#endif
+#ifdef MAPMESHATTRIBUTES_VERTEX_FEATURE_ID
+layout(location = 1) flat in uint v_FeatureId;
+#endif
+
+layout(location = 0) out vec4 o_Target;
+
+layout(set = 2, binding = 0) uniform MapMaterial_color {
+    vec4 color;
+};
+
+void main() {
+#ifdef MAPMESHATTRIBUTES_VERTEX_COLOR
+    // Vertex colors override the material color
+    o_Target = v_Color;
+#else
+    o_Target = color;
+#endif
+}
@@ -0,0 +1,41 @@
+#version 450
+
+layout(location = 0) in vec2 Vertex_Position;
+#ifdef MAPMESHATTRIBUTES_VERTEX_COLOR
+layout(location = 1) in vec4 Vertex_Color;
+layout(location = 0) out vec4 v_Color;
+#endif
+#ifdef MAPMESHATTRIBUTES_VERTEX_FEATURE_ID
+layout(location = 2) in uint Vertex_FeatureId;
+layout(location = 1) flat out uint v_FeatureId;
+#endif
+#ifdef MAPMESHATTRIBUTES_VERTEX_EXTRUSION
+layout(location = 3) in vec2 Vertex_Extrusion;
+#endif
+
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 ViewProj;
+};
+layout(set = 1, binding = 0) uniform Transform {
+    mat4 Model;
+};
+#ifdef MAPMESHATTRIBUTES_VERTEX_EXTRUSION
+layout(set = 2, binding = 1) uniform MapMaterial_camera_scale {
+    float camera_scale;
+};
+#endif
+
+void main() {
+#ifdef MAPMESHATTRIBUTES_VERTEX_COLOR
+    v_Color = Vertex_Color;
+#endif
+#ifdef MAPMESHATTRIBUTES_VERTEX_FEATURE_ID
+    v_FeatureId = Vertex_FeatureId;
+#endif
+    vec2 position = Vertex_Position;
+#ifdef MAPMESHATTRIBUTES_VERTEX_EXTRUSION
+    // Strokes keep their width in pixels at any camera scale
+    position += Vertex_Extrusion * camera_scale;
+#endif
+    gl_Position = ViewProj * Model * vec4(position, 0.0, 1.0);
+}
@@ -0,0 +1,24 @@
+#version 300 es
+precision highp float;
+
+#ifdef MAPMESHATTRIBUTES_VERTEX_COLOR
+in vec4 v_Color;
+#endif
+#ifdef MAPMESHATTRIBUTES_VERTEX_FEATURE_ID
+flat in uint v_FeatureId;
+#endif
+
+out vec4 o_Target;
+
+layout(std140) uniform MapMaterial_color {
+    vec4 color;
+};
+
+void main() {
+#ifdef MAPMESHATTRIBUTES_VERTEX_COLOR
+    // Vertex colors override the material color
+    o_Target = v_Color;
+#else
+    o_Target = color;
+#endif
+}
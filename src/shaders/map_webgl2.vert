@@ -0,0 +1,42 @@
+#version 300 es
+precision highp float;
+
+in vec2 Vertex_Position;
+#ifdef MAPMESHATTRIBUTES_VERTEX_COLOR
+in vec4 Vertex_Color;
+out vec4 v_Color;
+#endif
+#ifdef MAPMESHATTRIBUTES_VERTEX_FEATURE_ID
+in uint Vertex_FeatureId;
+flat out uint v_FeatureId;
+#endif
+#ifdef MAPMESHATTRIBUTES_VERTEX_EXTRUSION
+in vec2 Vertex_Extrusion;
+#endif
+
+layout(std140) uniform Camera {
+    mat4 ViewProj;
+};
+layout(std140) uniform Transform {
+    mat4 Model;
+};
+#ifdef MAPMESHATTRIBUTES_VERTEX_EXTRUSION
+layout(std140) uniform MapMaterial_camera_scale {
+    float camera_scale;
+};
+#endif
+
+void main() {
+#ifdef MAPMESHATTRIBUTES_VERTEX_COLOR
+    v_Color = Vertex_Color;
+#endif
+#ifdef MAPMESHATTRIBUTES_VERTEX_FEATURE_ID
+    v_FeatureId = Vertex_FeatureId;
+#endif
+    vec2 position = Vertex_Position;
+#ifdef MAPMESHATTRIBUTES_VERTEX_EXTRUSION
+    // Strokes keep their width in pixels at any camera scale
+    position += Vertex_Extrusion * camera_scale;
+#endif
+    gl_Position = ViewProj * Model * vec4(position, 0.0, 1.0);
+}
@@ -0,0 +1,153 @@
+//! Web mercator XYZ tile scheme and tile sources
+#[cfg(not(target_arch = "wasm32"))]
+use crate::crs::Crs;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::transform::MapCoord;
+#[cfg(not(target_arch = "wasm32"))]
+use bevy::prelude::*;
+#[cfg(not(target_arch = "wasm32"))]
+use std::f64::consts::PI;
+
+/// Half of the web mercator extent in meters
+#[cfg(not(target_arch = "wasm32"))]
+const MERCATOR_EXTENT: f64 = PI * 6_378_137.0;
+
+/// Maximal number of tiles loaded for a view
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) const MAX_TILES: u32 = 256;
+
+/// Source of tiles in the XYZ tile scheme
+#[derive(Clone, Debug)]
+pub enum TileSource {
+    /// Path template with `{z}`, `{x}` and `{y}` placeholders, e.g. `tiles/{z}/{x}/{y}.png`
+    Directory(String),
+    /// MBTiles file. Requires the `mbtiles` feature.
+    MBTiles(String),
+}
+
+/// Tile zoom level, column and row (from the top)
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) type TileId = (u8, u32, u32);
+
+/// Tiles of a zoom level covering an area
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) struct TileRange {
+    z: u8,
+    min: (u32, u32),
+    max: (u32, u32),
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl TileRange {
+    /// Tiles at zoom level `z` covering `bbox` in `crs`. Returns `None` for unsupported CRS.
+    pub(crate) fn covering(bbox: (f64, f64, f64, f64), crs: Crs, z: u8) -> Option<Self> {
+        let corners = [
+            MapCoord::new(bbox.0, bbox.1),
+            MapCoord::new(bbox.2, bbox.1),
+            MapCoord::new(bbox.0, bbox.3),
+            MapCoord::new(bbox.2, bbox.3),
+        ];
+        let mut range = TileRange {
+            z,
+            min: (u32::MAX, u32::MAX),
+            max: (0, 0),
+        };
+        for corner in &corners {
+            let (x, y) = tile_at(crs.transform_to(*corner, Crs::WEB_MERCATOR)?, z);
+            range.min = (range.min.0.min(x), range.min.1.min(y));
+            range.max = (range.max.0.max(x), range.max.1.max(y));
+        }
+        Some(range)
+    }
+
+    pub(crate) fn len(&self) -> u32 {
+        (self.max.0 - self.min.0 + 1) * (self.max.1 - self.min.1 + 1)
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = TileId> {
+        let (z, min, max) = (self.z, self.min, self.max);
+        (min.0..=max.0).flat_map(move |x| (min.1..=max.1).map(move |y| (z, x, y)))
+    }
+}
+
+/// Web mercator tile column and row containing `coord`
+#[cfg(not(target_arch = "wasm32"))]
+fn tile_at(coord: MapCoord, z: u8) -> (u32, u32) {
+    let tiles = (1u32 << z) as f64;
+    let size = 2.0 * MERCATOR_EXTENT / tiles;
+    let x = ((coord.x + MERCATOR_EXTENT) / size).floor();
+    let y = ((MERCATOR_EXTENT - coord.y) / size).floor();
+    (
+        x.max(0.0).min(tiles - 1.0) as u32,
+        y.max(0.0).min(tiles - 1.0) as u32,
+    )
+}
+
+/// Web mercator extent of a tile (minx, miny, maxx, maxy)
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn tile_bbox((z, x, y): TileId) -> (f64, f64, f64, f64) {
+    let size = 2.0 * MERCATOR_EXTENT / (1u32 << z) as f64;
+    let (min_x, max_y) = (
+        -MERCATOR_EXTENT + x as f64 * size,
+        MERCATOR_EXTENT - y as f64 * size,
+    );
+    (min_x, max_y - size, min_x + size, max_y)
+}
+
+/// Opened tile source
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) enum TileReader {
+    Directory(String),
+    #[cfg(feature = "mbtiles")]
+    MBTiles(rusqlite::Connection),
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl TileReader {
+    pub(crate) fn open(source: &TileSource) -> Option<Self> {
+        match source {
+            TileSource::Directory(template) => Some(TileReader::Directory(template.clone())),
+            #[cfg(feature = "mbtiles")]
+            TileSource::MBTiles(path) => {
+                let flags = rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY;
+                match rusqlite::Connection::open_with_flags(path, flags) {
+                    Ok(connection) => Some(TileReader::MBTiles(connection)),
+                    Err(err) => {
+                        warn!("Opening {} failed: {}", path, err);
+                        None
+                    }
+                }
+            }
+            #[cfg(not(feature = "mbtiles"))]
+            TileSource::MBTiles(path) => {
+                warn!("{}: MBTiles support requires the `mbtiles` feature", path);
+                None
+            }
+        }
+    }
+
+    /// Tile data as stored in the source
+    pub(crate) fn read(&self, (z, x, y): TileId) -> Option<Vec<u8>> {
+        match self {
+            TileReader::Directory(template) => {
+                let path = template
+                    .replace("{z}", &z.to_string())
+                    .replace("{x}", &x.to_string())
+                    .replace("{y}", &y.to_string());
+                std::fs::read(path).ok()
+            }
+            #[cfg(feature = "mbtiles")]
+            TileReader::MBTiles(connection) => {
+                // MBTiles rows are counted from the bottom (TMS)
+                let row = (1u32 << z) - 1 - y;
+                connection
+                    .query_row(
+                        "SELECT tile_data FROM tiles WHERE zoom_level=?1 AND tile_column=?2 AND tile_row=?3",
+                        rusqlite::params![z, x, row],
+                        |row| row.get(0),
+                    )
+                    .ok()
+            }
+        }
+    }
+}
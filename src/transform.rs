@@ -0,0 +1,237 @@
+//! Conversions between screen, world and map coordinates
+//!
+//! * Screen: window pixels with origin at the bottom left corner (like `CursorMoved`)
+//! * World: bevy world units. The world origin is at `Map.center`, one unit is one pixel
+//!   at the integer zoom level (level of detail). Fractional zoom levels are displayed
+//!   with the camera scale. The origin is moved to the view center when reloading
+//!   (floating origin), so world coordinates stay small enough for f32.
+//!   The camera may be rotated around the view axis (`Map.rotation`).
+//! * Map: coordinates in the dataset CRS
+use crate::map::Map;
+use bevy::prelude::*;
+
+/// Coordinate in map units
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MapCoord {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl MapCoord {
+    pub fn new(x: f64, y: f64) -> Self {
+        MapCoord { x, y }
+    }
+}
+
+/// Transformation between screen, world and map coordinates
+#[derive(Clone, Copy, Debug)]
+pub struct MapTransform {
+    /// Map coordinates of the world origin
+    pub center: MapCoord,
+    /// Map units per world unit
+    pub resolution: f64,
+    /// Camera position in world coordinates
+    pub camera: Vec2,
+    /// Camera scale (world units per screen pixel)
+    pub scale: f32,
+    /// Camera rotation around the view axis in radians (counter-clockwise)
+    pub rotation: f32,
+    /// Window size in pixels
+    pub window_size: Vec2,
+    /// Physical pixels per window pixel (HiDPI)
+    pub scale_factor: f32,
+}
+
+impl MapTransform {
+    /// Transformation with world origin at `center` and camera at origin
+    pub fn new(center: MapCoord, resolution: f64) -> Self {
+        MapTransform {
+            center,
+            resolution,
+            camera: Vec2::zero(),
+            scale: 1.0,
+            rotation: 0.0,
+            window_size: Vec2::zero(),
+            scale_factor: 1.0,
+        }
+    }
+
+    /// Transformation of the current map view
+    pub fn from_map(map: &Map) -> Self {
+        MapTransform::new(map.center, map.resolution())
+            .with_camera(map.offset.truncate(), map.scale())
+            .with_rotation(map.rotation)
+    }
+
+    pub fn with_camera(mut self, camera: Vec2, scale: f32) -> Self {
+        self.camera = camera;
+        self.scale = scale;
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: f32) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_window_size(mut self, window_size: Vec2) -> Self {
+        self.window_size = window_size;
+        self
+    }
+
+    pub fn with_scale_factor(mut self, scale_factor: f32) -> Self {
+        self.scale_factor = scale_factor;
+        self
+    }
+
+    pub fn screen_to_world(&self, screen: Vec2) -> Vec2 {
+        self.camera + rotate(screen - self.window_size / 2.0, self.rotation) * self.scale
+    }
+
+    pub fn world_to_screen(&self, world: Vec2) -> Vec2 {
+        rotate(world - self.camera, -self.rotation) / self.scale + self.window_size / 2.0
+    }
+
+    pub fn world_to_map(&self, world: Vec2) -> MapCoord {
+        MapCoord::new(
+            self.center.x + world.x as f64 * self.resolution,
+            self.center.y + world.y as f64 * self.resolution,
+        )
+    }
+
+    pub fn map_to_world(&self, coord: MapCoord) -> Vec2 {
+        let [x, y] = self.map_to_world_f64(coord);
+        Vec2::new(x as f32, y as f32)
+    }
+
+    /// World coordinates in full precision
+    pub fn map_to_world_f64(&self, coord: MapCoord) -> [f64; 2] {
+        [
+            (coord.x - self.center.x) / self.resolution,
+            (coord.y - self.center.y) / self.resolution,
+        ]
+    }
+
+    pub fn screen_to_map(&self, screen: Vec2) -> MapCoord {
+        self.world_to_map(self.screen_to_world(screen))
+    }
+
+    pub fn map_to_screen(&self, coord: MapCoord) -> Vec2 {
+        self.world_to_screen(self.map_to_world(coord))
+    }
+
+    /// Map units per screen pixel
+    pub fn pixel_size(&self) -> f64 {
+        self.resolution * self.scale as f64
+    }
+
+    /// Visible map extent (minx, miny, maxx, maxy)
+    pub fn view_bbox(&self) -> (f64, f64, f64, f64) {
+        let corners = [
+            Vec2::zero(),
+            Vec2::new(self.window_size.x, 0.0),
+            Vec2::new(0.0, self.window_size.y),
+            self.window_size,
+        ];
+        // Corners of the rotated view
+        let mut bbox = (
+            f64::INFINITY,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::NEG_INFINITY,
+        );
+        for corner in &corners {
+            let pos = self.screen_to_map(*corner);
+            bbox = (
+                bbox.0.min(pos.x),
+                bbox.1.min(pos.y),
+                bbox.2.max(pos.x),
+                bbox.3.max(pos.y),
+            );
+        }
+        bbox
+    }
+}
+
+/// Rotate `v` counter-clockwise by `angle` radians
+pub fn rotate(v: Vec2, angle: f32) -> Vec2 {
+    let (sin, cos) = angle.sin_cos();
+    Vec2::new(v.x * cos - v.y * sin, v.x * sin + v.y * cos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+
+    /// Rotated and zoomed view with the camera away from the world origin
+    fn view() -> MapTransform {
+        MapTransform::new(MapCoord::new(2_600_000.0, 1_200_000.0), 2.0)
+            .with_camera(Vec2::new(150.0, -80.0), 1.5)
+            .with_rotation(0.6)
+            .with_window_size(Vec2::new(800.0, 600.0))
+    }
+
+    fn assert_near(a: MapCoord, b: MapCoord, tolerance: f64) {
+        assert!(
+            (a.x - b.x).abs() <= tolerance && (a.y - b.y).abs() <= tolerance,
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn window_center_is_camera() {
+        let view = view();
+        let center = view.screen_to_world(view.window_size / 2.0);
+        assert!((center - view.camera).length() < 1e-4);
+    }
+
+    #[test]
+    fn screen_map_round_trip() {
+        let view = view();
+        for screen in &[
+            Vec2::zero(),
+            Vec2::new(800.0, 600.0),
+            Vec2::new(123.0, 456.0),
+        ] {
+            let back = view.map_to_screen(view.screen_to_map(*screen));
+            assert!(
+                (back - *screen).length() < 1e-3,
+                "{:?} != {:?}",
+                back,
+                screen
+            );
+        }
+    }
+
+    #[test]
+    fn world_map_round_trip() {
+        let view = view();
+        let world = Vec2::new(-300.5, 712.25);
+        assert!((view.map_to_world(view.world_to_map(world)) - world).length() < 1e-4);
+        let coord = MapCoord::new(2_600_123.5, 1_199_876.25);
+        assert_near(view.world_to_map(view.map_to_world(coord)), coord, 1e-3);
+    }
+
+    #[test]
+    fn rotated_view_bbox() {
+        // Quarter turn: the window width spans the map y axis
+        let view = MapTransform::new(MapCoord::new(1000.0, 2000.0), 2.0)
+            .with_camera(Vec2::new(100.0, 50.0), 1.0)
+            .with_rotation(FRAC_PI_2)
+            .with_window_size(Vec2::new(800.0, 600.0));
+        let (minx, miny, maxx, maxy) = view.view_bbox();
+        assert_near(
+            MapCoord::new(minx, miny),
+            MapCoord::new(600.0, 1300.0),
+            1e-3,
+        );
+        assert_near(
+            MapCoord::new(maxx, maxy),
+            MapCoord::new(1800.0, 2900.0),
+            1e-3,
+        );
+    }
+}
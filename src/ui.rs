@@ -0,0 +1,26 @@
+//! Map overlays
+use crate::cursor::{setup_cursor_position, update_cursor_position};
+use crate::scale_bar::{setup_scale_bar, update_scale_bar};
+use bevy::prelude::*;
+
+/// Overlay font
+pub(crate) const FONT: &str = "fonts/DejaVuSansMono.ttf";
+
+pub(crate) const FONT_SIZE: f32 = 14.0;
+
+fn spawn_ui_camera(commands: &mut Commands) {
+    commands.spawn(CameraUiBundle::default());
+}
+
+/// Map overlays: Cursor position and scale bar
+pub struct MapUiPlugin;
+
+impl Plugin for MapUiPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_startup_system(spawn_ui_camera.system())
+            .add_startup_system(setup_cursor_position.system())
+            .add_startup_system(setup_scale_bar.system())
+            .add_system(update_cursor_position.system())
+            .add_system(update_scale_bar.system());
+    }
+}
@@ -0,0 +1,345 @@
+//! Polygon and line validation and repair before tessellation
+use crate::mesh_builder::{Line, Polygon};
+
+type Ring = Vec<[f64; 2]>;
+
+/// Validate polygon and push the repaired polygon(s) to `valid`.
+///
+/// Removes repeated points, closes rings, drops degenerate rings, splits
+/// self-intersecting rings (bow-ties) and orients exterior rings counter-clockwise
+/// and interior rings clockwise. Returns `true` if the polygon was changed.
+pub fn validate_polygon(polygon: &Polygon, valid: &mut Vec<Polygon>) -> bool {
+    let mut changed = false;
+    let mut exteriors: Vec<Ring> = Vec::new();
+    let mut holes: Vec<Ring> = Vec::new();
+    for (idx, ring) in polygon.iter().enumerate() {
+        let mut ring = ring.clone();
+        changed |= remove_repeated_points(&mut ring);
+        changed |= close_ring(&mut ring);
+        let mut parts = Vec::new();
+        changed |= split_ring(ring, &mut parts);
+        for mut part in parts {
+            let area = ring_area(&part);
+            if part.len() < 4 || area == 0.0 {
+                changed = true;
+                continue;
+            }
+            // exterior rings counter-clockwise, holes clockwise
+            if (idx == 0) != (area > 0.0) {
+                part.reverse();
+                changed = true;
+            }
+            if idx == 0 {
+                exteriors.push(part);
+            } else {
+                holes.push(part);
+            }
+        }
+    }
+    if exteriors.len() == 1 {
+        let mut polygon = exteriors;
+        polygon.append(&mut holes);
+        valid.push(polygon);
+        return changed;
+    }
+    // Split exterior ring: assign holes to the containing part
+    let first = valid.len();
+    valid.extend(exteriors.into_iter().map(|exterior| vec![exterior]));
+    for hole in holes {
+        if let Some(polygon) = valid[first..]
+            .iter_mut()
+            .find(|polygon| contains_point(&polygon[0], hole[0]))
+        {
+            polygon.push(hole);
+        } else {
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Validate line and push the repaired line to `valid`.
+///
+/// Removes repeated points and drops degenerate lines. Returns `true` if the line
+/// was changed.
+pub fn validate_line(mut line: Line, valid: &mut Vec<Line>) -> bool {
+    let changed = remove_repeated_points(&mut line);
+    if line.len() < 2 {
+        return true;
+    }
+    valid.push(line);
+    changed
+}
+
+/// Remove consecutive duplicate points
+fn remove_repeated_points(ring: &mut Ring) -> bool {
+    let len = ring.len();
+    ring.dedup();
+    ring.len() != len
+}
+
+/// Append first point to unclosed rings
+fn close_ring(ring: &mut Ring) -> bool {
+    match (ring.first(), ring.last()) {
+        (Some(first), Some(last)) if first != last => {
+            let first = *first;
+            ring.push(first);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Split closed ring at self-intersections into simple rings
+fn split_ring(ring: Ring, parts: &mut Vec<Ring>) -> bool {
+    let (i, j, p) = match find_crossing(&ring) {
+        Some(crossing) => crossing,
+        None => {
+            parts.push(ring);
+            return false;
+        }
+    };
+    // Loop between the intersecting segments
+    let mut inner = Vec::with_capacity(j - i + 2);
+    inner.push(p);
+    inner.extend_from_slice(&ring[i + 1..=j]);
+    inner.push(p);
+    // Remaining ring
+    let mut outer = Vec::with_capacity(ring.len() - (j - i) + 1);
+    outer.extend_from_slice(&ring[..=i]);
+    outer.push(p);
+    outer.extend_from_slice(&ring[j + 1..]);
+    remove_repeated_points(&mut inner);
+    remove_repeated_points(&mut outer);
+    split_ring(outer, parts);
+    split_ring(inner, parts);
+    true
+}
+
+/// Find a proper intersection of two non-adjacent segments of a closed ring.
+/// Returns the segment indices `i < j` and the intersection point.
+///
+/// Sweeps the segments by x, so only segments with overlapping x extents are compared.
+fn find_crossing(ring: &[[f64; 2]]) -> Option<(usize, usize, [f64; 2])> {
+    let segments = ring.len().saturating_sub(1);
+    let extent = |i: usize| {
+        let (a, b) = (ring[i], ring[i + 1]);
+        (
+            a[0].min(b[0]),
+            a[0].max(b[0]),
+            a[1].min(b[1]),
+            a[1].max(b[1]),
+        )
+    };
+    let mut order: Vec<usize> = (0..segments).collect();
+    order.sort_by(|a, b| {
+        extent(*a)
+            .0
+            .partial_cmp(&extent(*b).0)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    // Segments overlapping the sweep position
+    let mut active: Vec<usize> = Vec::new();
+    for k in order {
+        let (min_x, _, min_y, max_y) = extent(k);
+        active.retain(|other| extent(*other).1 >= min_x);
+        for &other in &active {
+            let (i, j) = (k.min(other), k.max(other));
+            if j == i + 1 || (i == 0 && j == segments - 1) {
+                // adjacent, or adjacent via closing point
+                continue;
+            }
+            let (_, _, other_min_y, other_max_y) = extent(other);
+            if other_max_y < min_y || max_y < other_min_y {
+                continue;
+            }
+            if let Some(p) = intersection(ring[i], ring[i + 1], ring[j], ring[j + 1]) {
+                return Some((i, j, p));
+            }
+        }
+        active.push(k);
+    }
+    None
+}
+
+/// Proper intersection point of segments p1-p2 and q1-q2
+fn intersection(p1: [f64; 2], p2: [f64; 2], q1: [f64; 2], q2: [f64; 2]) -> Option<[f64; 2]> {
+    let r = [p2[0] - p1[0], p2[1] - p1[1]];
+    let s = [q2[0] - q1[0], q2[1] - q1[1]];
+    let denom = r[0] * s[1] - r[1] * s[0];
+    if denom == 0.0 {
+        return None;
+    }
+    let qp = [q1[0] - p1[0], q1[1] - p1[1]];
+    let t = (qp[0] * s[1] - qp[1] * s[0]) / denom;
+    let u = (qp[0] * r[1] - qp[1] * r[0]) / denom;
+    if t > 0.0 && t < 1.0 && u > 0.0 && u < 1.0 {
+        Some([p1[0] + t * r[0], p1[1] + t * r[1]])
+    } else {
+        None
+    }
+}
+
+/// Signed ring area (positive for counter-clockwise rings)
+fn ring_area(ring: &[[f64; 2]]) -> f64 {
+    ring.windows(2)
+        .map(|w| w[0][0] * w[1][1] - w[1][0] * w[0][1])
+        .sum::<f64>()
+        / 2.0
+}
+
+/// Point in ring test (even-odd rule)
+fn contains_point(ring: &[[f64; 2]], p: [f64; 2]) -> bool {
+    let mut inside = false;
+    for w in ring.windows(2) {
+        let (a, b) = (w[0], w[1]);
+        if (a[1] > p[1]) != (b[1] > p[1])
+            && p[0] < (b[0] - a[0]) * (p[1] - a[1]) / (b[1] - a[1]) + a[0]
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(min: f64, max: f64) -> Ring {
+        vec![[min, min], [max, min], [max, max], [min, max], [min, min]]
+    }
+
+    #[test]
+    fn removes_repeated_points() {
+        let mut ring = vec![[0.0, 0.0], [0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [1.0, 1.0]];
+        assert!(remove_repeated_points(&mut ring));
+        assert_eq!(ring, vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0]]);
+        assert!(!remove_repeated_points(&mut ring));
+    }
+
+    #[test]
+    fn closes_rings() {
+        let mut ring = vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0]];
+        assert!(close_ring(&mut ring));
+        assert_eq!(ring.last(), Some(&[0.0, 0.0]));
+        assert!(!close_ring(&mut ring));
+        assert_eq!(ring.len(), 4);
+    }
+
+    #[test]
+    fn keeps_valid_polygon() {
+        let mut hole = square(1.0, 2.0);
+        hole.reverse();
+        let polygon = vec![square(0.0, 4.0), hole];
+        let mut valid = Vec::new();
+        assert!(!validate_polygon(&polygon, &mut valid));
+        assert_eq!(valid, vec![polygon]);
+    }
+
+    #[test]
+    fn orients_rings() {
+        let mut exterior = square(0.0, 4.0);
+        exterior.reverse();
+        let polygon = vec![exterior, square(1.0, 2.0)];
+        let mut valid = Vec::new();
+        assert!(validate_polygon(&polygon, &mut valid));
+        assert_eq!(valid.len(), 1);
+        assert!(ring_area(&valid[0][0]) > 0.0);
+        assert!(ring_area(&valid[0][1]) < 0.0);
+    }
+
+    #[test]
+    fn repairs_unclosed_ring_with_repeated_points() {
+        let polygon = vec![vec![
+            [0.0, 0.0],
+            [1.0, 0.0],
+            [1.0, 0.0],
+            [1.0, 1.0],
+            [0.0, 1.0],
+        ]];
+        let mut valid = Vec::new();
+        assert!(validate_polygon(&polygon, &mut valid));
+        assert_eq!(valid, vec![vec![square(0.0, 1.0)]]);
+    }
+
+    #[test]
+    fn drops_degenerate_rings() {
+        let polygon = vec![vec![[0.0, 0.0], [1.0, 0.0], [2.0, 0.0], [0.0, 0.0]]];
+        let mut valid = Vec::new();
+        assert!(validate_polygon(&polygon, &mut valid));
+        assert!(valid.is_empty());
+    }
+
+    #[test]
+    fn splits_bow_tie() {
+        let polygon = vec![vec![
+            [0.0, 0.0],
+            [2.0, 2.0],
+            [2.0, 0.0],
+            [0.0, 2.0],
+            [0.0, 0.0],
+        ]];
+        let mut valid = Vec::new();
+        assert!(validate_polygon(&polygon, &mut valid));
+        assert_eq!(valid.len(), 2);
+        for part in &valid {
+            assert_eq!(part.len(), 1);
+            assert_eq!(part[0].len(), 4);
+            assert!((ring_area(&part[0]) - 1.0).abs() < 1e-9);
+            assert!(part[0].contains(&[1.0, 1.0]));
+        }
+    }
+
+    #[test]
+    fn assigns_holes_to_split_parts() {
+        let exterior = vec![[0.0, 0.0], [4.0, 4.0], [4.0, 0.0], [0.0, 4.0], [0.0, 0.0]];
+        let mut hole = vec![[2.8, 1.8], [3.2, 1.8], [3.2, 2.2], [2.8, 2.2], [2.8, 1.8]];
+        hole.reverse();
+        let polygon = vec![exterior, hole.clone()];
+        let mut valid = Vec::new();
+        validate_polygon(&polygon, &mut valid);
+        assert_eq!(valid.len(), 2);
+        let with_hole: Vec<_> = valid.iter().filter(|part| part.len() == 2).collect();
+        assert_eq!(with_hole.len(), 1);
+        assert_eq!(with_hole[0][1], hole);
+        assert!(contains_point(&with_hole[0][0], [3.0, 2.0]));
+    }
+
+    #[test]
+    fn drops_holes_outside_split_parts() {
+        let exterior = vec![[0.0, 0.0], [4.0, 4.0], [4.0, 0.0], [0.0, 4.0], [0.0, 0.0]];
+        let mut hole = square(1.8, 2.2);
+        hole[0] = [1.0, 0.2];
+        hole[4] = [1.0, 0.2];
+        let polygon = vec![exterior, hole];
+        let mut valid = Vec::new();
+        assert!(validate_polygon(&polygon, &mut valid));
+        assert!(valid.iter().all(|part| part.len() == 1));
+    }
+
+    #[test]
+    fn finds_no_crossing_in_simple_ring() {
+        let n = 1000;
+        let mut ring: Ring = (0..n)
+            .map(|i| {
+                let angle = i as f64 / n as f64 * std::f64::consts::PI * 2.0;
+                [angle.cos(), angle.sin()]
+            })
+            .collect();
+        ring.push(ring[0]);
+        assert!(find_crossing(&ring).is_none());
+        let mut parts = Vec::new();
+        assert!(!split_ring(ring, &mut parts));
+        assert_eq!(parts.len(), 1);
+    }
+
+    #[test]
+    fn drops_degenerate_lines() {
+        let mut valid = Vec::new();
+        assert!(validate_line(vec![[0.0, 0.0], [0.0, 0.0]], &mut valid));
+        assert!(valid.is_empty());
+        assert!(!validate_line(vec![[0.0, 0.0], [1.0, 0.0]], &mut valid));
+        assert_eq!(valid.len(), 1);
+    }
+}
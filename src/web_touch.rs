@@ -0,0 +1,73 @@
+//! Touch input of the web build
+//!
+//! winit's web backend reports touches as pointer events, which bevy receives as
+//! `CursorMoved` and mouse button events without the pointer id. Touch pointer events
+//! on the canvas are forwarded as `TouchInput` events instead, so gestures work with
+//! the `Touches` resource like on native platforms.
+use bevy::input::touch::{TouchInput, TouchPhase};
+use bevy::prelude::*;
+use std::sync::{Arc, Mutex};
+use wasm_bindgen::{closure::Closure, JsCast};
+
+/// Touch events received from the browser since the last frame
+#[derive(Clone, Default)]
+pub(crate) struct TouchQueue(Arc<Mutex<Vec<TouchInput>>>);
+
+/// Touch input from a pointer event on the canvas. Positions are in logical pixels
+/// with origin at the top left of the canvas, like winit touch positions.
+fn touch_input(event: &web_sys::PointerEvent, phase: TouchPhase) -> Option<TouchInput> {
+    if event.pointer_type() != "touch" {
+        return None;
+    }
+    let target = event.target()?.dyn_into::<web_sys::Element>().ok()?;
+    if target.tag_name() != "CANVAS" {
+        return None;
+    }
+    let rect = target.get_bounding_client_rect();
+    Some(TouchInput {
+        phase,
+        position: Vec2::new(
+            (event.client_x() as f64 - rect.left()) as f32,
+            (event.client_y() as f64 - rect.top()) as f32,
+        ),
+        force: None,
+        id: event.pointer_id() as u64,
+    })
+}
+
+/// Listen for touch pointer events of the document
+pub(crate) fn setup_touch_events(queue: Res<TouchQueue>) {
+    let document = match web_sys::window().and_then(|window| window.document()) {
+        Some(document) => document,
+        None => return,
+    };
+    let phases = [
+        ("pointerdown", TouchPhase::Started),
+        ("pointermove", TouchPhase::Moved),
+        ("pointerup", TouchPhase::Ended),
+        ("pointercancel", TouchPhase::Cancelled),
+    ];
+    for (name, phase) in phases.iter().cloned() {
+        let queue = queue.0.clone();
+        let listener = Closure::wrap(Box::new(move |event: web_sys::PointerEvent| {
+            if let Some(touch) = touch_input(&event, phase) {
+                queue.lock().unwrap().push(touch);
+            }
+        }) as Box<dyn FnMut(_)>);
+        if document
+            .add_event_listener_with_callback(name, listener.as_ref().unchecked_ref())
+            .is_err()
+        {
+            warn!("Listening for {} events failed", name);
+        }
+        // Listen for the lifetime of the page
+        listener.forget();
+    }
+}
+
+/// Send the received touch events to bevy
+pub(crate) fn send_touch_events(queue: Res<TouchQueue>, mut events: ResMut<Events<TouchInput>>) {
+    for touch in queue.0.lock().unwrap().drain(..) {
+        events.send(touch);
+    }
+}